@@ -1,7 +1,9 @@
-#![allow(clippy::module_name_repetitions)]
+#![allow(clippy::module_name_repetitions, clippy::struct_excessive_bools)]
 
 use clap::{Parser, Subcommand, ValueHint};
 
+use crate::internal::rpc::SearchBy;
+
 #[derive(Debug, Clone, Parser)]
 #[clap(bin_name = "ame", name = "Amethyst", version = env ! ("CARGO_PKG_VERSION"), about = env ! ("CARGO_PKG_DESCRIPTION"), infer_subcommands = true, allow_external_subcommands = true, allow_hyphen_values = true)]
 pub struct Args {
@@ -51,6 +53,46 @@ pub enum Operation {
     #[clap(bin_name = "ame", name = "upgrade", visible_aliases = & ["-Syu"])]
     Upgrade(UpgradeArgs),
 
+    /// Lists previous transactions or shows the details of one
+    #[clap(bin_name = "ame", name = "history")]
+    History(HistoryArgs),
+
+    /// Installs a previous version of a package from pacman's cache or amethyst's archive
+    #[clap(bin_name = "ame", name = "downgrade")]
+    Downgrade(DowngradeArgs),
+
+    /// Reverts all package changes made by a recorded transaction
+    #[clap(bin_name = "ame", name = "rollback")]
+    Rollback(RollbackArgs),
+
+    /// Lists available repo and AUR updates without root or syncing the system databases
+    #[clap(bin_name = "ame", name = "checkupdates")]
+    CheckUpdates(CheckUpdatesArgs),
+
+    /// Manages periodic background update checks
+    #[clap(bin_name = "ame", name = "daemon")]
+    Daemon(DaemonArgs),
+
+    /// Finds AUR packages broken by shared library or Python upgrades and rebuilds them
+    #[clap(bin_name = "ame", name = "rebuild-check")]
+    RebuildCheck,
+
+    /// Downloads the PKGBUILDs of AUR packages without building them
+    #[clap(bin_name = "ame", name = "getpkgbuild")]
+    GetPkgbuild(GetPkgbuildArgs),
+
+    /// Builds AUR packages and their dependencies, leaving the packages in a directory instead of installing them
+    #[clap(bin_name = "ame", name = "build")]
+    Build(BuildArgs),
+
+    /// Opens the latest build log of an AUR package
+    #[clap(bin_name = "ame", name = "log")]
+    Log(LogArgs),
+
+    /// Shows the latest Arch Linux news
+    #[clap(bin_name = "ame", name = "news")]
+    News(NewsArgs),
+
     /// Generates shell completions for supported shells (bash, fish, elvish, pwsh)
     #[clap(bin_name = "ame", name = "gencomp", visible_aliases = & ["-g"])]
     GenComp(GenCompArgs),
@@ -73,7 +115,7 @@ impl Default for Operation {
 #[derive(Default, Debug, Clone, Parser)]
 pub struct InstallArgs {
     /// The name of the package(s) to install
-    #[clap(required = true)]
+    #[clap(required_unless_present = "resume")]
     pub packages: Vec<String>,
 
     /// Installs only from the AUR [-Sa]
@@ -83,6 +125,18 @@ pub struct InstallArgs {
     /// Install the packages only from the pacman-defined repositories [-Sr]
     #[clap(long, short)]
     pub repo: bool,
+
+    /// Keeps building other AUR packages when one fails, skipping only the ones that depend on it
+    #[clap(long)]
+    pub keep_going: bool,
+
+    /// Skips the `check()` function of AUR packages, and installing their checkdepends
+    #[clap(long)]
+    pub nocheck: bool,
+
+    /// Resumes the last AUR install that didn't finish, reusing the packages it already built
+    #[clap(long, conflicts_with = "packages")]
+    pub resume: bool,
 }
 
 #[derive(Default, Debug, Clone, Parser)]
@@ -102,6 +156,10 @@ pub struct SearchArgs {
     #[clap(long, short)]
     pub repo: bool,
 
+    /// Searches the AUR by the given field instead of name and description, e.g. maintainer, depends, provides or keywords
+    #[clap(long, short)]
+    pub by: Option<SearchBy>,
+
     /// The string the package must match in the search
     #[clap(required = true)]
     pub search: Vec<String>,
@@ -116,6 +174,10 @@ pub struct QueryArgs {
     /// Lists repo/native packages [-Qr, -Qn]
     #[clap(long, short)]
     pub repo: bool,
+
+    /// Lists foreign packages that are no longer in the AUR or any repo
+    #[clap(long, short)]
+    pub orphaned_foreign: bool,
 }
 
 #[derive(Default, Debug, Clone, Parser)]
@@ -134,6 +196,137 @@ pub struct UpgradeArgs {
     /// Upgrades only from the AUR
     #[clap(long, short)]
     pub aur: bool,
+
+    /// Starts AUR upgrades from the last background update check instead of checking again
+    #[clap(long)]
+    pub cached: bool,
+
+    /// Keeps building other AUR packages when one fails, skipping only the ones that depend on it
+    #[clap(long)]
+    pub keep_going: bool,
+
+    /// Skips the `check()` function of AUR packages, and installing their checkdepends
+    #[clap(long)]
+    pub nocheck: bool,
+}
+
+#[derive(Default, Debug, Clone, Parser)]
+pub struct HistoryArgs {
+    /// The id of a transaction to show in detail
+    pub id: Option<usize>,
+
+    /// Only lists transactions that touched the given package
+    #[clap(long, short)]
+    pub package: Option<String>,
+
+    /// Only lists transactions of the given kind (install, remove, upgrade, clean, downgrade, rollback)
+    #[clap(long, short)]
+    pub kind: Option<String>,
+
+    /// Only lists the given number of most recent transactions
+    #[clap(long, short = 'n')]
+    pub limit: Option<usize>,
+}
+
+#[derive(Default, Debug, Clone, Parser)]
+pub struct DowngradeArgs {
+    /// The name of the package to downgrade
+    #[clap(required = true)]
+    pub package: String,
+}
+
+#[derive(Default, Debug, Clone, Parser)]
+pub struct RollbackArgs {
+    /// The id of the transaction to roll back, as listed by `ame history`
+    #[clap(required = true)]
+    pub id: usize,
+}
+
+#[derive(Default, Debug, Clone, Parser)]
+pub struct CheckUpdatesArgs {
+    /// Only checks repo/native packages
+    #[clap(long, short)]
+    pub repo: bool,
+
+    /// Only checks AUR packages
+    #[clap(long, short)]
+    pub aur: bool,
+
+    /// Prints the updates as JSON
+    #[clap(long)]
+    pub json: bool,
+}
+
+#[derive(Debug, Clone, Parser)]
+pub struct DaemonArgs {
+    #[clap(subcommand)]
+    pub action: DaemonAction,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum DaemonAction {
+    /// Installs and enables a systemd user timer that checks for updates periodically
+    #[clap(name = "install-timer")]
+    InstallTimer(InstallTimerArgs),
+
+    /// Disables and removes the update check timer
+    #[clap(name = "remove-timer")]
+    RemoveTimer,
+
+    /// Checks for updates once, caches the result and notifies about new updates
+    #[clap(name = "check")]
+    Check,
+}
+
+#[derive(Default, Debug, Clone, Parser)]
+pub struct InstallTimerArgs {
+    /// How often to check for updates, as a systemd time span
+    #[clap(long, short, default_value = "1h")]
+    pub interval: String,
+}
+
+#[derive(Default, Debug, Clone, Parser)]
+pub struct GetPkgbuildArgs {
+    /// The name of the package(s) to download the PKGBUILDs of
+    #[clap(required = true)]
+    pub packages: Vec<String>,
+
+    /// The directory to clone into, defaults to the current directory
+    #[clap(long, short, value_hint = ValueHint::DirPath)]
+    pub output: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, Parser)]
+pub struct BuildArgs {
+    /// The name of the package(s) to build
+    #[clap(required = true)]
+    pub packages: Vec<String>,
+
+    /// The directory to put the built packages in, defaults to the current directory
+    #[clap(long, short, value_hint = ValueHint::DirPath)]
+    pub output: Option<String>,
+
+    /// Keeps building other AUR packages when one fails, skipping only the ones that depend on it
+    #[clap(long)]
+    pub keep_going: bool,
+
+    /// Skips the `check()` function of AUR packages, and installing their checkdepends
+    #[clap(long)]
+    pub nocheck: bool,
+}
+
+#[derive(Default, Debug, Clone, Parser)]
+pub struct LogArgs {
+    /// The package to show the build log of
+    #[clap(required = true)]
+    pub package: String,
+}
+
+#[derive(Default, Debug, Clone, Parser)]
+pub struct NewsArgs {
+    /// Only shows the given number of most recent posts
+    #[clap(long, short = 'n')]
+    pub limit: Option<usize>,
 }
 
 #[derive(Default, Debug, Clone, Parser)]
@@ -0,0 +1,162 @@
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+
+use crate::crash;
+use crate::internal::config;
+use crate::internal::exit_code::AppExitCode;
+use crate::internal::utils::vercmp;
+
+const ARCHIVE_DIR: &str = ".local/share/ame/archive";
+const PACMAN_CACHE: &str = "/var/cache/pacman/pkg";
+/// How many versions of each package the archive keeps unless configured otherwise, like `paccache`
+const ARCHIVE_KEEP: usize = 3;
+
+#[derive(Debug, Clone)]
+/// A package file found in pacman's cache or amethyst's archive
+pub struct CachedPackage {
+    pub name: String,
+    pub version: String,
+    pub path: String,
+}
+
+/// Returns the directory amethyst keeps built AUR packages in
+pub fn archive_dir() -> String {
+    format!("{}/{}", env::var("HOME").unwrap(), ARCHIVE_DIR)
+}
+
+/// Splits a package file name like `foo-1:1.0-1-x86_64.pkg.tar.zst` into name and version
+pub fn parse_filename(filename: &str) -> Option<(String, String)> {
+    if Path::new(filename)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("sig"))
+    {
+        return None;
+    }
+    let base = &filename[..filename.find(".pkg.tar")?];
+
+    // The name itself may contain dashes, so split from the right: arch, pkgrel, pkgver, name
+    let mut parts = base.rsplitn(4, '-');
+    let _arch = parts.next()?;
+    let pkgrel = parts.next()?;
+    let pkgver = parts.next()?;
+    let name = parts.next()?;
+
+    Some((name.to_string(), format!("{pkgver}-{pkgrel}")))
+}
+
+/// Copies every package built in the given build directory into the archive, dropping old versions
+pub fn archive_built(builddir: &str) {
+    let keep = config::read().base.archive_keep.unwrap_or(ARCHIVE_KEEP);
+    if keep == 0 {
+        return;
+    }
+    let archive = archive_dir();
+    fs::create_dir_all(&archive).unwrap_or_else(|e| {
+        crash!(
+            AppExitCode::FailedCreatingPaths,
+            "Couldn't create path: {}: {}",
+            archive,
+            e
+        );
+    });
+
+    let mut archived: Vec<String> = vec![];
+    let pkgdirs = fs::read_dir(builddir).into_iter().flatten().flatten();
+    for pkgdir in pkgdirs.filter(|d| d.path().is_dir()) {
+        for file in fs::read_dir(pkgdir.path()).into_iter().flatten().flatten() {
+            let filename = file.file_name().to_string_lossy().to_string();
+            if let Some((name, _)) = parse_filename(&filename) {
+                fs::copy(file.path(), Path::new(&archive).join(&filename)).ok();
+                archived.push(name);
+            }
+        }
+    }
+
+    archived.sort();
+    archived.dedup();
+    for pkg in archived {
+        prune(&archive, &pkg, keep);
+    }
+}
+
+/// Removes all but the `keep` newest versions of a package from the archive
+fn prune(archive: &str, pkg: &str, keep: usize) {
+    let mut versions = fs::read_dir(archive)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|file| {
+            let (name, version) = parse_filename(&file.file_name().to_string_lossy())?;
+            (name == pkg).then(|| (version, file.path()))
+        })
+        .collect::<Vec<(String, PathBuf)>>();
+    versions.sort_by(|(a, _), (b, _)| vercmp(b, a));
+
+    for (_, path) in versions.into_iter().skip(keep) {
+        fs::remove_file(path).ok();
+    }
+}
+
+/// Lists all versions of a package available in pacman's cache and amethyst's archive, newest first
+pub fn cached_versions(pkg: &str) -> Vec<CachedPackage> {
+    let mut versions: Vec<CachedPackage> = vec![];
+
+    for dir in [PACMAN_CACHE.to_string(), archive_dir()] {
+        for file in fs::read_dir(&dir).into_iter().flatten().flatten() {
+            let filename = file.file_name().to_string_lossy().to_string();
+            if let Some((name, version)) = parse_filename(&filename) {
+                if name == pkg && !versions.iter().any(|v| v.version == version) {
+                    versions.push(CachedPackage {
+                        name,
+                        version,
+                        path: file.path().to_string_lossy().to_string(),
+                    });
+                }
+            }
+        }
+    }
+
+    versions.sort_by(|a, b| vercmp(&b.version, &a.version));
+    versions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(name: &str, version: &str) -> (String, String) {
+        (name.to_string(), version.to_string())
+    }
+
+    #[test]
+    fn plain() {
+        assert_eq!(
+            parse_filename("foo-1.0-1-x86_64.pkg.tar.zst"),
+            Some(parsed("foo", "1.0-1"))
+        );
+    }
+
+    #[test]
+    fn epoch() {
+        assert_eq!(
+            parse_filename("foo-2:1.0-3-any.pkg.tar.xz"),
+            Some(parsed("foo", "2:1.0-3"))
+        );
+    }
+
+    #[test]
+    fn dashed_name() {
+        assert_eq!(
+            parse_filename("python-foo-bar-git-r12.abc-1-x86_64.pkg.tar.zst"),
+            Some(parsed("python-foo-bar-git", "r12.abc-1"))
+        );
+    }
+
+    #[test]
+    fn signatures_and_others() {
+        assert_eq!(parse_filename("foo-1.0-1-x86_64.pkg.tar.zst.sig"), None);
+        assert_eq!(parse_filename("foo-1.0-1-x86_64.pkg.tar.zst.SIG"), None);
+        assert_eq!(parse_filename("foo-1.0.tar.gz"), None);
+        assert_eq!(parse_filename("foo-1.0-x86_64.pkg.tar.zst"), None);
+    }
+}
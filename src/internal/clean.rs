@@ -1,23 +1,19 @@
-use regex::Regex;
-
 use crate::{log, Options};
 
+/// Strips a version constraint like `>=1.0` from a dependency, leaving the package name
+pub fn dep_name(dep: &str) -> &str {
+    dep.split(['<', '>', '=']).next().unwrap_or(dep)
+}
+
 /// Strips packages from versioning and other extraneous information.
 pub fn clean(a: &[String], options: Options) -> Vec<String> {
-    // Strip versioning from package names
-    let r = Regex::new(r"(\S+)((?:>=|<=|>|<|=\W)\S+$)").unwrap();
-    let mut cleaned: Vec<String> = vec![];
     let verbosity = options.verbosity;
 
-    // Push cleaned package names to vector
-    for b in a {
-        if r.captures_iter(b).count() > 0 {
-            let c = r.captures(b).unwrap().get(1).map_or("", |m| m.as_str());
-            cleaned.push(c.to_string());
-        } else {
-            cleaned.push(b.to_string());
-        }
-    }
+    // Strip versioning from package names
+    let cleaned = a
+        .iter()
+        .map(|b| dep_name(b).to_string())
+        .collect::<Vec<String>>();
 
     if verbosity >= 1 {
         log!("Cleaned: {:?}\nInto: {:?}", a, cleaned);
@@ -25,3 +21,24 @@ pub fn clean(a: &[String], options: Options) -> Vec<String> {
 
     cleaned
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constraints() {
+        assert_eq!(dep_name("foo"), "foo");
+        assert_eq!(dep_name("foo=1.0"), "foo");
+        assert_eq!(dep_name("foo>=1.0-2"), "foo");
+        assert_eq!(dep_name("foo<=1:1.0"), "foo");
+        assert_eq!(dep_name("foo>1.0"), "foo");
+        assert_eq!(dep_name("foo<1.0"), "foo");
+    }
+
+    #[test]
+    fn names_with_symbols() {
+        assert_eq!(dep_name("libc++>=15"), "libc++");
+        assert_eq!(dep_name("python-foo.bar_baz=1"), "python-foo.bar_baz");
+    }
+}
@@ -1,6 +1,8 @@
 use std::ffi::{OsStr, OsString};
-use std::fs;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
 use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread;
 
 use crate::internal::config;
 use crate::internal::error::{AppError, AppResult};
@@ -18,6 +20,8 @@ pub struct ShellCommand {
     command: String,
     args: Vec<OsString>,
     elevated: bool,
+    working_dir: Option<String>,
+    envs: Vec<(String, String)>,
 }
 
 impl ShellCommand {
@@ -52,6 +56,46 @@ impl ShellCommand {
         Self::new("bash")
     }
 
+    pub fn fakeroot() -> Self {
+        Self::new("fakeroot")
+    }
+
+    pub fn notify_send() -> Self {
+        Self::new("notify-send")
+    }
+
+    pub fn systemctl() -> Self {
+        Self::new("systemctl")
+    }
+
+    pub fn snapper() -> Self {
+        Self::new("snapper")
+    }
+
+    pub fn timeshift() -> Self {
+        Self::new("timeshift")
+    }
+
+    pub fn repo_add() -> Self {
+        Self::new("repo-add")
+    }
+
+    pub fn cp() -> Self {
+        Self::new("cp")
+    }
+
+    pub fn install() -> Self {
+        Self::new("install")
+    }
+
+    pub fn sed() -> Self {
+        Self::new("sed")
+    }
+
+    pub fn expac() -> Self {
+        Self::new("expac")
+    }
+
     pub fn sudo() -> Self {
         Self::new(&config::read().bin.sudo.unwrap_or_default())
     }
@@ -61,6 +105,8 @@ impl ShellCommand {
             command: command.to_string(),
             args: Vec::new(),
             elevated: false,
+            working_dir: None,
+            envs: Vec::new(),
         }
     }
 
@@ -83,6 +129,20 @@ impl ShellCommand {
         self
     }
 
+    /// Runs the command in the given directory
+    pub fn working_dir<S: Into<String>>(mut self, dir: S) -> Self {
+        self.working_dir = Some(dir.into());
+
+        self
+    }
+
+    /// Sets an environment variable for the command
+    pub fn env<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.envs.push((key.into(), value.into()));
+
+        self
+    }
+
     /// Runs the command with sudo
     pub const fn elevated(mut self) -> Self {
         self.elevated = true;
@@ -122,27 +182,89 @@ impl ShellCommand {
         })
     }
 
+    /// Waits for the child to exit with its output going to the given log file instead of the terminal
+    pub fn wait_into_log(self, log: &str) -> AppResult<ExitStatus> {
+        let log = OpenOptions::new().create(true).append(true).open(log)?;
+        let mut child = self.spawn_with(Stdio::null(), log.try_clone()?.into(), log.into())?;
+
+        child.wait().map_err(AppError::from)
+    }
+
+    /// Waits for the child to exit while copying its output to both the terminal and the given log files
+    pub fn wait_logged<S: AsRef<str>>(self, logs: &[S]) -> AppResult<ExitStatus> {
+        let logs = logs
+            .iter()
+            .map(|log| {
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(log.as_ref())
+            })
+            .collect::<io::Result<Vec<File>>>()?;
+        let mut child = self.spawn(true)?;
+
+        let (stdout, stderr) = (child.stdout.take().unwrap(), child.stderr.take().unwrap());
+        let stdout_logs = logs
+            .iter()
+            .map(File::try_clone)
+            .collect::<io::Result<Vec<File>>>()?;
+        let handles = [
+            thread::spawn(move || tee(stdout, io::stdout(), stdout_logs)),
+            thread::spawn(move || tee(stderr, io::stderr(), logs)),
+        ];
+        for handle in handles {
+            handle.join().ok();
+        }
+
+        child.wait().map_err(AppError::from)
+    }
+
     fn spawn(self, piped: bool) -> AppResult<Child> {
         let (stdout, stderr) = if piped {
             (Stdio::piped(), Stdio::piped())
         } else {
             (Stdio::inherit(), Stdio::inherit())
         };
-        let child = if self.elevated {
-            Command::new(&config::read().bin.sudo.unwrap_or_default())
-                .arg(self.command)
-                .args(self.args)
-                .stdout(stdout)
-                .stderr(stderr)
-                .spawn()?
+
+        self.spawn_with(Stdio::inherit(), stdout, stderr)
+    }
+
+    fn spawn_with(self, stdin: Stdio, stdout: Stdio, stderr: Stdio) -> AppResult<Child> {
+        let mut command = if self.elevated {
+            let mut command = Command::new(config::read().bin.sudo.unwrap_or_default());
+            command.arg(self.command);
+            command
         } else {
             Command::new(self.command)
-                .args(self.args)
-                .stdout(stdout)
-                .stderr(stderr)
-                .spawn()?
         };
+        if let Some(dir) = self.working_dir {
+            command.current_dir(dir);
+        }
+        command.envs(self.envs);
+        let child = command
+            .args(self.args)
+            .stdin(stdin)
+            .stdout(stdout)
+            .stderr(stderr)
+            .spawn()?;
 
         Ok(child)
     }
 }
+
+/// Copies everything read from `from` to both `to` and the logs
+fn tee<R: Read, W: Write>(mut from: R, mut to: W, mut logs: Vec<File>) {
+    let mut buf = [0; 4096];
+    loop {
+        match from.read(&mut buf) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => {
+                to.write_all(&buf[..n]).ok();
+                to.flush().ok();
+                for log in &mut logs {
+                    log.write_all(&buf[..n]).ok();
+                }
+            }
+        }
+    }
+}
@@ -1,8 +1,10 @@
 #![allow(clippy::module_name_repetitions)]
 
 use serde::Deserialize;
+use std::collections::BTreeMap;
 use std::{env, fs};
 
+use crate::internal::hooks::Snapshot;
 use crate::{crash, AppExitCode};
 
 #[derive(Debug, Deserialize)]
@@ -10,6 +12,13 @@ pub struct Config {
     pub base: ConfigBase,
     pub extra: ConfigExtra,
     pub bin: ConfigBin,
+    #[serde(default)]
+    pub hooks: ConfigHooks,
+    pub local_repo: Option<ConfigLocalRepo>,
+    #[serde(default)]
+    pub sources: Vec<ConfigSource>,
+    #[serde(default)]
+    pub package: BTreeMap<String, ConfigPackage>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -17,6 +26,24 @@ pub struct ConfigBase {
     pub pacdiff_warn: bool,
     pub highlight_optdepends: bool,
     pub powerpill: bool,
+    #[serde(default)]
+    pub ignore: Vec<String>,
+    pub news_url: Option<String>,
+    pub jobs: Option<usize>,
+    pub source_priority: Option<Vec<String>>,
+    #[serde(default)]
+    pub remove_make_deps: RemoveMakeDeps,
+    pub archive_keep: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// What to do with dependencies that were only installed to build AUR packages
+pub enum RemoveMakeDeps {
+    #[default]
+    Ask,
+    Always,
+    Never,
 }
 
 #[derive(Debug, Deserialize)]
@@ -31,6 +58,59 @@ pub struct ConfigBin {
     pub sudo: Option<String>,
 }
 
+#[derive(Debug, Default, Deserialize)]
+pub struct ConfigHooks {
+    #[serde(default)]
+    pub pre_transaction: Vec<String>,
+    #[serde(default)]
+    pub post_transaction: Vec<String>,
+    pub snapshot: Option<Snapshot>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ConfigLocalRepo {
+    pub name: String,
+    pub path: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ConfigSource {
+    pub name: String,
+    pub url: Option<String>,
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ConfigPackage {
+    #[serde(default)]
+    pub makepkg_flags: Vec<String>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    pub makepkg_conf: Option<String>,
+    #[serde(default)]
+    pub skip_review: bool,
+    #[serde(default)]
+    pub nocheck: bool,
+}
+
+impl Config {
+    /// Returns the `[package.<name>]` settings of a package base, set for either the base or one of its packages
+    pub fn package_settings(&self, base: &str, packages: &[String]) -> ConfigPackage {
+        self.package
+            .get(base)
+            .or_else(|| packages.iter().find_map(|p| self.package.get(p)))
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+impl ConfigPackage {
+    /// Whether the package is built without running `check()`
+    pub fn nocheck(&self) -> bool {
+        self.nocheck || self.makepkg_flags.iter().any(|f| f == "--nocheck")
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
@@ -38,6 +118,12 @@ impl Default for Config {
                 pacdiff_warn: true,
                 highlight_optdepends: true,
                 powerpill: false,
+                ignore: vec![],
+                news_url: None,
+                jobs: None,
+                source_priority: None,
+                remove_make_deps: RemoveMakeDeps::Ask,
+                archive_keep: None,
             },
             extra: ConfigExtra {
                 uwu: None,
@@ -47,6 +133,10 @@ impl Default for Config {
             bin: ConfigBin {
                 sudo: Some("sudo".to_string()),
             },
+            hooks: ConfigHooks::default(),
+            local_repo: None,
+            sources: vec![],
+            package: BTreeMap::new(),
         }
     }
 }
@@ -9,5 +9,8 @@ pub enum AppExitCode {
     GitError = 6,
     MakePkgError = 7,
     ConfigParseError = 8,
+    NoUpdates = 9,
+    HookFailed = 10,
+    RpcError = 11,
     Other = 63,
 }
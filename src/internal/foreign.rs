@@ -0,0 +1,299 @@
+use colored::Colorize;
+use std::collections::BTreeMap;
+use std::process::{Command, Stdio};
+
+use crate::internal::commands::ShellCommand;
+use crate::internal::config;
+use crate::internal::dep_name;
+use crate::internal::error::SilentUnwrap;
+use crate::internal::exit_code::AppExitCode;
+use crate::internal::query::{installed, local_repo_packages};
+use crate::internal::rpc::{rpcsearch_by, SearchBy};
+use crate::internal::sources;
+use crate::{log, warn, Options};
+
+#[derive(Debug, Clone)]
+/// An installed package as listed by `pacman -Q`
+pub struct QueriedPackage {
+    pub name: String,
+    pub version: String,
+}
+
+#[derive(Debug)]
+/// A foreign package that no longer exists in the AUR or any sync repo
+pub struct OrphanedPackage {
+    pub name: String,
+    pub version: String,
+    pub successors: Vec<String>,
+}
+
+#[derive(Debug)]
+/// A foreign package that is now available from a sync repo
+pub struct MovedPackage {
+    pub name: String,
+    pub repo_package: String,
+    pub relation: &'static str,
+}
+
+/// Lists non-native packages using `pacman -Qm`, plus those installed from the local repo
+pub fn list_foreign(options: Options) -> Vec<QueriedPackage> {
+    let verbosity = options.verbosity;
+
+    let non_native = ShellCommand::pacman()
+        .arg("-Qm")
+        .args(["--color", "never"])
+        .wait_with_output()
+        .silent_unwrap(AppExitCode::PacmanError);
+
+    // Collect by lines to a Vec<String>
+    let mut non_native = non_native.stdout.split('\n').collect::<Vec<&str>>();
+
+    // Remove last element, which is an empty line
+    non_native.pop();
+
+    // Parse non-native packages into a Vec<QueriedPackage>
+    let mut parsed_non_native: Vec<QueriedPackage> = vec![];
+    for pkg in non_native {
+        // Split by space
+        let split = pkg.split(' ').collect::<Vec<&str>>();
+        if verbosity >= 1 {
+            log!("{:?}", split);
+        }
+        // Create QueriedPackage and push it to parsed_non_native
+        let name = split[0].to_string();
+        let version = split[1].to_string();
+        parsed_non_native.push(QueriedPackage { name, version });
+    }
+
+    let local_repo = local_repo_packages();
+    if !local_repo.is_empty() {
+        add_installed(&mut parsed_non_native, &local_repo, &installed());
+    }
+
+    if verbosity >= 1 {
+        log!("{:?}", &parsed_non_native);
+    }
+
+    parsed_non_native
+}
+
+/// Adds the installed ones of the given packages that aren't listed yet
+fn add_installed(
+    packages: &mut Vec<QueriedPackage>,
+    names: &[String],
+    installed: &BTreeMap<String, String>,
+) {
+    for name in names {
+        if let Some(version) = installed.get(name) {
+            if !packages.iter().any(|pkg| &pkg.name == name) {
+                packages.push(QueriedPackage {
+                    name: name.clone(),
+                    version: version.clone(),
+                });
+            }
+        }
+    }
+}
+
+/// Lists every sync repo package alongside the packages it replaces and provides
+pub fn repo_relations() -> Vec<(String, Vec<String>, Vec<String>)> {
+    // The local repo only holds packages built from the AUR
+    let local_repo = config::read().local_repo.map(|repo| repo.name);
+    let output = ShellCommand::expac()
+        .args(["-S", "%r\t%n\t%R\t%P"])
+        .wait_with_output()
+        .silent_unwrap(AppExitCode::PacmanError)
+        .stdout;
+
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split('\t');
+            let repo = parts.next()?;
+            if local_repo.as_deref() == Some(repo) {
+                return None;
+            }
+            let name = parts.next()?.to_string();
+            let names = |field: Option<&str>| {
+                field
+                    .unwrap_or_default()
+                    .split_whitespace()
+                    .map(|dep| dep_name(dep).to_string())
+                    .collect::<Vec<String>>()
+            };
+            let replaces = names(parts.next());
+            let provides = names(parts.next());
+            Some((name, replaces, provides))
+        })
+        .collect()
+}
+
+/// Lists installed packages that weren't validated against a sync repo, which is the case for anything built locally
+fn locally_built() -> Vec<String> {
+    let output = ShellCommand::pacman()
+        .args(["-Qi", "--color", "never"])
+        .env("LC_ALL", "C")
+        .wait_with_output()
+        .silent_unwrap(AppExitCode::PacmanError)
+        .stdout;
+
+    let mut built = vec![];
+    let mut name = "";
+    for line in output.lines() {
+        match line.split_once(':').map(|(k, v)| (k.trim(), v.trim())) {
+            Some(("Name", value)) => name = value,
+            Some(("Validated By", "None")) => built.push(name.to_string()),
+            _ => {}
+        }
+    }
+
+    built
+}
+
+/// Finds foreign packages that a sync repo now ships, provides or replaces
+pub fn find_moved(foreign: &[QueriedPackage], options: Options) -> Vec<MovedPackage> {
+    let verbosity = options.verbosity;
+    let relations = repo_relations();
+
+    // `pacman -Qm` leaves out packages named like a sync repo package, so find built ones sharing a name separately
+    let mut moved = locally_built()
+        .into_iter()
+        .filter(|pkg| relations.iter().any(|(name, _, _)| name == pkg))
+        .map(|pkg| MovedPackage {
+            name: pkg.clone(),
+            repo_package: pkg,
+            relation: "same name",
+        })
+        .collect::<Vec<MovedPackage>>();
+
+    // Prefer a package replacing it over one merely providing it
+    moved.extend(foreign.iter().filter_map(|pkg| {
+        let replacing = relations
+            .iter()
+            .find(|(_, replaces, _)| replaces.contains(&pkg.name))
+            .map(|(name, _, _)| (name, "replaces"));
+        let providing = || {
+            relations
+                .iter()
+                .find(|(_, _, provides)| provides.contains(&pkg.name))
+                .map(|(name, _, _)| (name, "provides"))
+        };
+        replacing
+            .or_else(providing)
+            .map(|(name, relation)| MovedPackage {
+                name: pkg.name.clone(),
+                repo_package: name.clone(),
+                relation,
+            })
+    }));
+
+    if verbosity >= 1 {
+        log!("Foreign packages now in the repos: {:?}", &moved);
+    }
+
+    moved
+}
+
+/// Checks whether a package exists in any sync repo
+fn in_sync_repos(pkg: &str) -> bool {
+    Command::new("pacman")
+        .arg("-Si")
+        .arg(pkg)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+/// Finds successors for foreign packages missing from the AUR, dropping any still in a sync repo
+/// or built from a custom source or local PKGBUILD
+pub fn find_orphaned(missing: Vec<QueriedPackage>, options: Options) -> Vec<OrphanedPackage> {
+    let verbosity = options.verbosity;
+    let missing = missing
+        .into_iter()
+        .filter(|pkg| !in_sync_repos(&pkg.name) && sources::find_installed(&pkg.name).is_none())
+        .collect::<Vec<QueriedPackage>>();
+    if missing.is_empty() {
+        return vec![];
+    }
+
+    let relations = repo_relations();
+
+    missing
+        .into_iter()
+        .map(|pkg| {
+            // Repo packages declaring `replaces` for it
+            let mut successors = relations
+                .iter()
+                .filter(|(_, replaces, _)| replaces.contains(&pkg.name))
+                .map(|(name, _, _)| format!("repo/{name}"))
+                .collect::<Vec<String>>();
+
+            // AUR packages declaring `Replaces` for it
+            successors.extend(
+                rpcsearch_by(&pkg.name, SearchBy::Replaces)
+                    .results
+                    .into_iter()
+                    .map(|p| format!("aur/{}", p.name)),
+            );
+
+            if verbosity >= 1 {
+                log!("Successors for {}: {:?}", pkg.name, &successors);
+            }
+
+            OrphanedPackage {
+                name: pkg.name,
+                version: pkg.version,
+                successors,
+            }
+        })
+        .collect()
+}
+
+/// Warns the user about foreign packages that can no longer be updated
+pub fn report_orphaned(orphaned: &[OrphanedPackage]) {
+    for pkg in orphaned {
+        warn!(
+            "{} {} is no longer in the AUR or any repo and won't receive updates",
+            pkg.name, pkg.version
+        );
+        if !pkg.successors.is_empty() {
+            println!(
+                "  {} {}",
+                "Possible successors:".bold(),
+                pkg.successors.join(", ")
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queried(name: &str, version: &str) -> QueriedPackage {
+        QueriedPackage {
+            name: name.to_string(),
+            version: version.to_string(),
+        }
+    }
+
+    #[test]
+    fn adds_installed_local_repo_packages() {
+        // `foo` came from `pacman -Qm`, `bar` was installed from the local repo, `baz` is only in the repo
+        let mut packages = vec![queried("foo", "1.0-1")];
+        let installed = [("foo", "1.0-1"), ("bar", "2.0-1"), ("pacman", "6.0.2-1")]
+            .iter()
+            .map(|&(name, version)| (name.to_string(), version.to_string()))
+            .collect::<BTreeMap<String, String>>();
+        let local_repo = vec!["foo".to_string(), "bar".to_string(), "baz".to_string()];
+
+        add_installed(&mut packages, &local_repo, &installed);
+
+        let listed = packages
+            .iter()
+            .map(|pkg| (pkg.name.as_str(), pkg.version.as_str()))
+            .collect::<Vec<(&str, &str)>>();
+        assert_eq!(listed, vec![("foo", "1.0-1"), ("bar", "2.0-1")]);
+    }
+}
@@ -0,0 +1,286 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Formatter};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::{env, fs};
+
+use crate::crash;
+use crate::internal::exit_code::AppExitCode;
+use crate::internal::query::{foreign, installed};
+use crate::internal::utils::vercmp;
+
+const HISTORY_FILE: &str = ".local/share/ame/history.jsonl";
+
+/// The transaction currently being recorded, if any
+static CURRENT: Mutex<Option<Pending>> = Mutex::new(None);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+/// The type of operation a transaction was recorded for
+pub enum TransactionKind {
+    Install,
+    Remove,
+    Upgrade,
+    Clean,
+    Downgrade,
+    Rollback,
+}
+
+impl Display for TransactionKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let kind = match self {
+            Self::Install => "install",
+            Self::Remove => "remove",
+            Self::Upgrade => "upgrade",
+            Self::Clean => "clean",
+            Self::Downgrade => "downgrade",
+            Self::Rollback => "rollback",
+        };
+        Display::fmt(kind, f)
+    }
+}
+
+impl FromStr for TransactionKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "install" => Ok(Self::Install),
+            "remove" => Ok(Self::Remove),
+            "upgrade" => Ok(Self::Upgrade),
+            "clean" => Ok(Self::Clean),
+            "downgrade" => Ok(Self::Downgrade),
+            "rollback" => Ok(Self::Rollback),
+            _ => Err(format!(
+                "{s} is not one of install, remove, upgrade, clean, downgrade or rollback"
+            )),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+/// Where a package in a transaction came from
+pub enum Source {
+    Repo,
+    Aur,
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let source = match self {
+            Self::Repo => "repo",
+            Self::Aur => "aur",
+        };
+        Display::fmt(source, f)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+/// What happened to a package in a transaction
+pub enum Outcome {
+    Installed,
+    Upgraded,
+    Downgraded,
+    Removed,
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+/// A single package touched by a transaction
+pub struct PackageChange {
+    pub name: String,
+    pub old_version: Option<String>,
+    pub new_version: Option<String>,
+    pub source: Source,
+    pub outcome: Outcome,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+/// An entry in the transaction history log
+pub struct Transaction {
+    pub id: usize,
+    pub timestamp: i64,
+    pub command: String,
+    pub kind: TransactionKind,
+    pub success: bool,
+    /// Whether the sync repos were upgraded, which only upgrades do
+    #[serde(default)]
+    pub synced: bool,
+    pub packages: Vec<PackageChange>,
+}
+
+/// State captured when a transaction begins, compared against the system when it ends
+struct Pending {
+    kind: TransactionKind,
+    timestamp: i64,
+    synced: bool,
+    targets: Vec<String>,
+    installed: BTreeMap<String, String>,
+    foreign: BTreeSet<String>,
+}
+
+fn history_path() -> String {
+    format!("{}/{}", env::var("HOME").unwrap(), HISTORY_FILE)
+}
+
+/// Starts recording a transaction. Nested transactions are folded into the outermost one.
+pub fn begin(kind: TransactionKind, targets: &[String]) {
+    if CURRENT.lock().unwrap().is_some() {
+        return;
+    }
+
+    // Query pacman before taking the lock, a crash while holding it would deadlock in `fail()`
+    let pending = Pending {
+        kind,
+        timestamp: chrono::Local::now().timestamp(),
+        synced: false,
+        targets: targets.to_vec(),
+        installed: installed(),
+        foreign: foreign(),
+    };
+    *CURRENT.lock().unwrap() = Some(pending);
+}
+
+/// Marks the current transaction as having upgraded the sync repos successfully
+pub fn synced() {
+    if let Some(pending) = CURRENT.lock().unwrap().as_mut() {
+        pending.synced = true;
+    }
+}
+
+/// Finishes recording the current transaction and appends it to the history log
+pub fn finish() {
+    let pending = CURRENT.lock().unwrap().take();
+    if let Some(pending) = pending {
+        record(&pending, true);
+    }
+}
+
+/// Records the current transaction as failed. Called when amethyst crashes mid-transaction.
+pub fn fail() {
+    fail_with(&[]);
+}
+
+/// Records the current transaction as failed, listing the given packages among the failed ones
+pub fn fail_with(packages: &[String]) {
+    let pending = CURRENT.lock().unwrap().take();
+    if let Some(mut pending) = pending {
+        pending.targets.extend(packages.iter().cloned());
+        record(&pending, false);
+    }
+}
+
+fn record(pending: &Pending, success: bool) {
+    let installed = installed();
+    let foreign = foreign();
+
+    // Collect every package whose version changed, plus targets that were left untouched
+    let mut names: BTreeSet<&String> = pending.installed.keys().chain(installed.keys()).collect();
+    names.retain(|name| pending.installed.get(*name) != installed.get(*name));
+    if !success {
+        names.extend(pending.targets.iter());
+    }
+
+    let packages = names
+        .into_iter()
+        .map(|name| {
+            let old_version = pending.installed.get(name).cloned();
+            let new_version = installed.get(name).cloned();
+            let source = if foreign.contains(name) || pending.foreign.contains(name) {
+                Source::Aur
+            } else {
+                Source::Repo
+            };
+            let outcome = match (&old_version, &new_version) {
+                (None, Some(_)) => Outcome::Installed,
+                (Some(_), None) => Outcome::Removed,
+                (Some(old), Some(new)) if old != new => {
+                    if vercmp(old, new) == Ordering::Less {
+                        Outcome::Upgraded
+                    } else {
+                        Outcome::Downgraded
+                    }
+                }
+                _ => Outcome::Failed,
+            };
+
+            PackageChange {
+                name: name.clone(),
+                old_version,
+                new_version,
+                source,
+                outcome,
+            }
+        })
+        .collect();
+
+    let transaction = Transaction {
+        id: read().len() + 1,
+        timestamp: pending.timestamp,
+        command: env::args().collect::<Vec<String>>().join(" "),
+        kind: pending.kind,
+        success,
+        synced: pending.synced,
+        packages,
+    };
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(history_path())
+        .unwrap_or_else(|e| {
+            crash!(
+                AppExitCode::FailedCreatingPaths,
+                "Couldn't open history file {}: {}",
+                history_path(),
+                e
+            );
+        });
+    writeln!(file, "{}", serde_json::to_string(&transaction).unwrap()).unwrap_or_else(|e| {
+        crash!(
+            AppExitCode::Other,
+            "Couldn't write to history file {}: {}",
+            history_path(),
+            e
+        );
+    });
+}
+
+/// Reads all recorded transactions, oldest first
+pub fn read() -> Vec<Transaction> {
+    fs::read_to_string(history_path())
+        .unwrap_or_default()
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            serde_json::from_str(line).unwrap_or_else(|e| {
+                crash!(
+                    AppExitCode::Other,
+                    "Could not parse history file {}: {}",
+                    history_path(),
+                    e
+                );
+            })
+        })
+        .collect()
+}
+
+/// Returns the transaction with the given id
+pub fn get(id: usize) -> Option<Transaction> {
+    read().into_iter().find(|t| t.id == id)
+}
+
+/// Returns the time of the last transaction that upgraded the sync repos successfully
+pub fn last_synced() -> Option<i64> {
+    read()
+        .into_iter()
+        .filter(|t| t.synced)
+        .map(|t| t.timestamp)
+        .next_back()
+}
@@ -0,0 +1,184 @@
+use serde::Deserialize;
+use std::process::Command;
+use std::sync::Mutex;
+
+use crate::internal::commands::ShellCommand;
+use crate::internal::config;
+use crate::internal::exit_code::AppExitCode;
+use crate::internal::history::TransactionKind;
+use crate::{crash, info, log, warn, Options};
+
+/// The outermost transaction hooks are currently running around, if any
+static ACTIVE: Mutex<Option<Active>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// Built-in snapshot presets that run alongside the configured hooks
+pub enum Snapshot {
+    Snapper,
+    Timeshift,
+}
+
+struct Active {
+    kind: TransactionKind,
+    packages: Vec<String>,
+    depth: usize,
+    snapper_pre: Option<String>,
+    verbosity: usize,
+}
+
+/// Runs a single hook command with the transaction described through environment variables
+fn run_hook(stage: &str, command: &str, kind: TransactionKind, packages: &[String]) -> bool {
+    Command::new("bash")
+        .args(["-c", command])
+        .env("AME_HOOK", stage)
+        .env("AME_TRANSACTION", kind.to_string())
+        .env("AME_PACKAGES", packages.join(" "))
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+/// Creates a snapshot using the configured preset, returning snapper's pre snapshot number
+fn snapshot(preset: Snapshot, stage: &str, active: &Active) -> Result<Option<String>, String> {
+    let description = format!("ame {} {}", active.kind, active.packages.join(" "));
+
+    match (preset, stage) {
+        (Snapshot::Snapper, "pre") => {
+            let output = ShellCommand::snapper()
+                .elevated()
+                .args(["create", "--type", "pre", "--print-number"])
+                .args(["--cleanup-algorithm", "number", "--description"])
+                .arg(description.trim())
+                .wait_with_output()
+                .map_err(|e| e.to_string())?;
+            if !output.status.success() {
+                return Err(output.stderr.trim().to_string());
+            }
+            Ok(Some(output.stdout.trim().to_string()))
+        }
+        (Snapshot::Snapper, _) => {
+            let Some(pre_number) = &active.snapper_pre else {
+                return Ok(None);
+            };
+            ShellCommand::snapper()
+                .elevated()
+                .args(["create", "--type", "post", "--pre-number", pre_number])
+                .args(["--cleanup-algorithm", "number", "--description"])
+                .arg(description.trim())
+                .wait_success()
+                .map_err(|e| e.to_string())?;
+            Ok(None)
+        }
+        (Snapshot::Timeshift, "pre") => {
+            ShellCommand::timeshift()
+                .elevated()
+                .args(["--create", "--comments"])
+                .arg(description.trim())
+                .wait_success()
+                .map_err(|e| e.to_string())?;
+            Ok(None)
+        }
+        // Timeshift has no notion of paired snapshots, the pre snapshot is the restore point
+        (Snapshot::Timeshift, _) => Ok(None),
+    }
+}
+
+/// Runs the `pre_transaction` hooks, crashing if any of them fails.
+/// Nested transactions, like dependencies installed during an upgrade, only add their packages
+/// to the outermost one.
+pub fn pre_transaction(kind: TransactionKind, packages: &[String], options: Options) {
+    let verbosity = options.verbosity;
+
+    // Don't hold the lock while running anything, a crash while holding it would deadlock in `fail()`
+    if let Some(active) = ACTIVE.lock().unwrap().as_mut() {
+        active.depth += 1;
+        for pkg in packages {
+            if !active.packages.contains(pkg) {
+                active.packages.push(pkg.clone());
+            }
+        }
+        return;
+    }
+
+    let mut active = Active {
+        kind,
+        packages: packages.to_vec(),
+        depth: 1,
+        snapper_pre: None,
+        verbosity,
+    };
+    let hooks = config::read().hooks;
+
+    if let Some(preset) = hooks.snapshot {
+        info!("Creating {:?} snapshot before {}", preset, kind);
+        active.snapper_pre = snapshot(preset, "pre", &active).unwrap_or_else(|e| {
+            crash!(
+                AppExitCode::HookFailed,
+                "Couldn't create snapshot, aborting: {}",
+                e
+            );
+        });
+    }
+
+    // From here on the post hooks have to run, even if amethyst crashes
+    *ACTIVE.lock().unwrap() = Some(active);
+
+    for hook in &hooks.pre_transaction {
+        if verbosity >= 1 {
+            log!("Running pre-transaction hook: {}", hook);
+        }
+        if !run_hook("pre", hook, kind, packages) {
+            crash!(
+                AppExitCode::HookFailed,
+                "Pre-transaction hook `{}` failed, aborting",
+                hook
+            );
+        }
+    }
+}
+
+/// Runs the `post_transaction` hooks once the outermost transaction is done
+pub fn post_transaction(options: Options) {
+    let mut guard = ACTIVE.lock().unwrap();
+
+    let active = match guard.as_mut() {
+        Some(active) if active.depth > 1 => {
+            active.depth -= 1;
+            return;
+        }
+        Some(_) => guard.take().unwrap(),
+        None => return,
+    };
+    drop(guard);
+
+    run_post(&active, options.verbosity);
+}
+
+/// Runs the `post_transaction` hooks of the transaction amethyst crashed in, however deeply nested.
+/// Called when amethyst crashes mid-transaction, so every pre snapshot gets its post snapshot.
+pub fn fail() {
+    let active = ACTIVE.lock().unwrap().take();
+    if let Some(active) = active {
+        run_post(&active, active.verbosity);
+    }
+}
+
+/// Runs the `post_transaction` hooks and snapshot for a finished transaction
+fn run_post(active: &Active, verbosity: usize) {
+    let hooks = config::read().hooks;
+
+    for hook in &hooks.post_transaction {
+        if verbosity >= 1 {
+            log!("Running post-transaction hook: {}", hook);
+        }
+        if !run_hook("post", hook, active.kind, &active.packages) {
+            warn!("Post-transaction hook `{}` failed", hook);
+        }
+    }
+
+    if let Some(preset) = hooks.snapshot {
+        if let Err(e) = snapshot(preset, "post", active) {
+            warn!("Couldn't create post-transaction snapshot: {}", e);
+        }
+    }
+}
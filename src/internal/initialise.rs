@@ -60,12 +60,41 @@ pub fn init(options: Options) {
 pacdiff_warn = true
 highlight_optdepends = true
 powerpill = false
+ignore = []
+jobs = 1
+remove_make_deps = \"ask\" # or \"always\" or \"never\"
+archive_keep = 3 # versions of each built package kept for downgrading, 0 turns archiving off
+# Where packages are looked for first, defaults to the repos, then [[sources]], then the AUR
+# source_priority = [\"repo\", \"company\", \"aur\"]
 
 [extra]
 review_user_shell = false
 
 [bin]
 sudo = \"sudo\"
+
+[hooks]
+pre_transaction = []
+post_transaction = []
+# snapshot = \"snapper\" # or \"timeshift\"
+
+# Add built AUR packages to a local repo and install them from there, it has to be in pacman.conf too
+# [local_repo]
+# name = \"ame-local\"
+# path = \"/var/cache/ame/repo\"
+
+# Extra PKGBUILD trees, one directory per package base with a .SRCINFO, from a git repo or a local path
+# [[sources]]
+# name = \"company\"
+# url = \"git+https://git.example.com/pkgbuilds.git\" # or path = \"/srv/abs\"
+
+# Settings for building a single package (or package base)
+# [package.example]
+# makepkg_flags = [\"--nocheck\"]
+# env = { MAKEFLAGS = \"-j8\" }
+# makepkg_conf = \"/etc/makepkg-custom.conf\"
+# skip_review = true
+# nocheck = true
 ";
 
     if !Path::new(&format!("{}/.config/ame/config.toml", homedir)).exists() {
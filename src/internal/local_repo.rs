@@ -0,0 +1,75 @@
+use std::path::Path;
+
+use crate::crash;
+use crate::internal::commands::ShellCommand;
+use crate::internal::config::ConfigLocalRepo;
+use crate::internal::exit_code::AppExitCode;
+use crate::internal::pacman_conf;
+
+const SYNC_DIR: &str = "/var/lib/pacman/sync";
+
+/// Makes sure pacman knows about the local repo, since packages added to it are installed through pacman
+pub fn check(repo: &ConfigLocalRepo) {
+    if !pacman_conf::read().repos.contains(&repo.name) {
+        crash!(
+            AppExitCode::ConfigParseError,
+            "The local repo [{}] isn't in pacman.conf, add it with:\n\n[{}]\nSigLevel = Optional TrustAll\nServer = file://{}\n",
+            repo.name,
+            repo.name,
+            repo.path
+        );
+    }
+}
+
+/// Adds built package files to the local repo and refreshes pacman's copy of its database.
+/// Only this repo's database is refreshed, so installing from it never causes a partial upgrade.
+pub fn add(repo: &ConfigLocalRepo, files: &[String], log: &str) -> Result<(), String> {
+    let run = |command: ShellCommand, what: &str| {
+        let status = command.wait_logged(&[log]).map_err(|e| e.to_string())?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(format!("{what} failed ({status})"))
+        }
+    };
+
+    // Copy the packages next to the database, repo-add only records where they are
+    run(
+        ShellCommand::install().elevated().args(["-d", &repo.path]),
+        "Creating the local repo",
+    )?;
+    let names = files
+        .iter()
+        .map(|file| {
+            let filename = file.rsplit('/').next().unwrap_or_default();
+            format!("{}/{}", repo.path, filename)
+        })
+        .collect::<Vec<String>>();
+    run(
+        ShellCommand::cp()
+            .elevated()
+            .arg("-f")
+            .args(files)
+            .arg(&repo.path),
+        "Copying packages into the local repo",
+    )?;
+
+    // Replace older versions of the same packages, removing their files
+    let db = format!("{}/{}.db.tar.gz", repo.path, repo.name);
+    run(
+        ShellCommand::repo_add()
+            .elevated()
+            .args(["-R", &db])
+            .args(&names),
+        "repo-add",
+    )?;
+
+    // `cp` follows the `.db` symlink, giving pacman the database itself
+    run(
+        ShellCommand::cp()
+            .elevated()
+            .arg(format!("{}/{}.db", repo.path, repo.name))
+            .arg(Path::new(SYNC_DIR).join(format!("{}.db", repo.name))),
+        "Refreshing the local repo",
+    )
+}
@@ -0,0 +1,277 @@
+use std::path::Path;
+use std::{env, fs};
+
+use crate::crash;
+use crate::internal::commands::ShellCommand;
+use crate::internal::error::SilentUnwrap;
+use crate::internal::exit_code::AppExitCode;
+
+const GIT_CACHE: &str = ".cache/ame/git";
+/// Which PKGBUILD directory or git URL each package installed from one came from
+const INSTALLED_FROM: &str = ".local/share/ame/installed_from";
+
+#[derive(Debug, Clone, Default)]
+/// What a PKGBUILD's .SRCINFO says about the packages it builds
+pub struct Srcinfo {
+    pub base: String,
+    pub version: String,
+    pub names: Vec<String>,
+    pub depends: Vec<String>,
+    pub make_depends: Vec<String>,
+    pub check_depends: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+/// A PKGBUILD that isn't in the AUR, from a local directory or a git repo
+pub struct LocalSource {
+    /// The directory holding the PKGBUILD
+    pub dir: String,
+    pub info: Srcinfo,
+}
+
+/// Returns whether an install target is a PKGBUILD directory or git URL rather than a package name
+pub fn is_local_source(target: &str) -> bool {
+    target.starts_with("git+")
+        || (target.starts_with('.') || target.contains('/'))
+            && Path::new(target).join("PKGBUILD").is_file()
+}
+
+/// Parses the contents of a .SRCINFO file
+pub fn parse_srcinfo(srcinfo: &str) -> Srcinfo {
+    let mut info = Srcinfo::default();
+    let (mut pkgver, mut pkgrel, mut epoch) = (String::new(), String::new(), None);
+
+    for line in srcinfo.lines() {
+        let (key, value) = match line.trim().split_once(" = ") {
+            Some((key, value)) => (key, value.to_string()),
+            None => continue,
+        };
+        match key {
+            "pkgbase" => info.base = value,
+            "pkgname" => info.names.push(value),
+            "pkgver" => pkgver = value,
+            "pkgrel" => pkgrel = value,
+            "epoch" => epoch = Some(value),
+            // Split packages can add their own dependencies, build all of them
+            "depends" if !info.depends.contains(&value) => info.depends.push(value),
+            "makedepends" if !info.make_depends.contains(&value) => info.make_depends.push(value),
+            "checkdepends" if !info.check_depends.contains(&value) => {
+                info.check_depends.push(value);
+            }
+            _ => {}
+        }
+    }
+
+    info.version = epoch.map_or_else(
+        || format!("{pkgver}-{pkgrel}"),
+        |epoch| format!("{epoch}:{pkgver}-{pkgrel}"),
+    );
+
+    info
+}
+
+/// Turns a git URL into a directory name, escaping everything but letters, digits, `-`, `_` and `.`
+/// so that different URLs never share a clone
+fn url_dir_name(url: &str) -> String {
+    url.bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() || b"-_.".contains(&b) {
+                (b as char).to_string()
+            } else {
+                format!("%{b:02X}")
+            }
+        })
+        .collect()
+}
+
+/// Clones or updates a git repo holding a PKGBUILD, returning where it is
+fn fetch_git(url: &str) -> String {
+    let dir = format!(
+        "{}/{}/{}",
+        env::var("HOME").unwrap(),
+        GIT_CACHE,
+        url_dir_name(url)
+    );
+
+    let git = if Path::new(&dir).join(".git").exists() {
+        ShellCommand::git().args(["-C", &dir, "pull", "--ff-only"])
+    } else {
+        ShellCommand::git().args(["clone", url, &dir])
+    };
+    let status = git.wait().silent_unwrap(AppExitCode::GitError);
+    if !status.success() {
+        crash!(AppExitCode::GitError, "Failed to clone {}", url);
+    }
+
+    dir
+}
+
+/// Loads a PKGBUILD directory or git URL, reading its .SRCINFO.
+/// Only local directories may have one generated with makepkg, PKGBUILDs from git mustn't run before review.
+pub fn load(target: &str) -> LocalSource {
+    target.strip_prefix("git+").map_or_else(
+        || load_dir(&canonical_target(target), true),
+        |url| load_dir(&fetch_git(url), false),
+    )
+}
+
+/// Resolves a PKGBUILD directory to an absolute path, leaving git URLs alone
+fn canonical_target(target: &str) -> String {
+    if target.starts_with("git+") {
+        return target.to_string();
+    }
+    fs::canonicalize(target).map_or_else(|_| target.to_string(), |p| p.display().to_string())
+}
+
+fn installed_from_path() -> String {
+    format!("{}/{}", env::var("HOME").unwrap(), INSTALLED_FROM)
+}
+
+/// Reads which directory or git URL each package was installed from
+fn read_installed_from() -> Vec<(String, String)> {
+    fs::read_to_string(installed_from_path())
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .map(|(name, target)| (name.to_string(), target.to_string()))
+        .collect()
+}
+
+/// Remembers the directory or git URL a PKGBUILD's packages are installed from, so upgrades can find it again
+pub fn remember(target: &str, source: &LocalSource) {
+    let target = canonical_target(target);
+    let mut installed_from = read_installed_from();
+    installed_from.retain(|(name, _)| !source.info.names.contains(name));
+    installed_from.extend(
+        source
+            .info
+            .names
+            .iter()
+            .map(|name| (name.clone(), target.clone())),
+    );
+
+    let path = installed_from_path();
+    let contents = installed_from
+        .iter()
+        .map(|(name, target)| format!("{name}\t{target}"))
+        .collect::<Vec<String>>()
+        .join("\n");
+    fs::write(&path, contents).unwrap_or_else(|e| {
+        crash!(
+            AppExitCode::FailedCreatingPaths,
+            "Couldn't write {}: {}",
+            path,
+            e
+        );
+    });
+}
+
+/// Loads the PKGBUILD a package was installed from, if it was installed from a directory or git URL
+/// and that is still there
+pub fn installed_from(name: &str) -> Option<LocalSource> {
+    let (_, target) = read_installed_from().into_iter().find(|(n, _)| n == name)?;
+    if !target.starts_with("git+") && !Path::new(&target).join("PKGBUILD").is_file() {
+        return None;
+    }
+
+    Some(load(&target))
+}
+
+/// Loads the PKGBUILD in a directory, reading its .SRCINFO.
+/// A missing .SRCINFO is generated with makepkg if `generate` is set, which runs the PKGBUILD.
+pub fn load_dir(dir: &str, generate: bool) -> LocalSource {
+    if !Path::new(dir).join("PKGBUILD").is_file() {
+        crash!(AppExitCode::MissingDeps, "No PKGBUILD found in {}", dir);
+    }
+
+    let srcinfo = match fs::read_to_string(Path::new(dir).join(".SRCINFO")) {
+        Ok(srcinfo) => srcinfo,
+        Err(_) if generate => {
+            ShellCommand::makepkg()
+                .working_dir(dir)
+                .arg("--printsrcinfo")
+                .wait_with_output()
+                .silent_unwrap(AppExitCode::MakePkgError)
+                .stdout
+        }
+        Err(_) => crash!(
+            AppExitCode::MakePkgError,
+            "{} has no .SRCINFO, which is needed to read it without running the PKGBUILD before review",
+            dir
+        ),
+    };
+    let info = parse_srcinfo(&srcinfo);
+    if info.base.is_empty() || info.names.is_empty() {
+        crash!(
+            AppExitCode::MakePkgError,
+            "Couldn't read the package information of {}",
+            dir
+        );
+    }
+
+    LocalSource {
+        dir: dir.to_string(),
+        info,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPLIT: &str = "\
+pkgbase = foo
+\tpkgdesc = Foo and its docs
+\tpkgver = 1.2.3
+\tpkgrel = 2
+\tepoch = 1
+\tmakedepends = cmake
+\tmakedepends = git
+\tcheckdepends = python-pytest
+\tdepends = glibc
+\tdepends = libc++>=15
+
+pkgname = foo
+
+pkgname = foo-docs
+\tdepends = glibc
+\tdepends = foo=1:1.2.3
+";
+
+    #[test]
+    fn split_package() {
+        let info = parse_srcinfo(SPLIT);
+        assert_eq!(info.base, "foo");
+        assert_eq!(info.names, vec!["foo", "foo-docs"]);
+        assert_eq!(info.version, "1:1.2.3-2");
+        assert_eq!(info.depends, vec!["glibc", "libc++>=15", "foo=1:1.2.3"]);
+        assert_eq!(info.make_depends, vec!["cmake", "git"]);
+        assert_eq!(info.check_depends, vec!["python-pytest"]);
+    }
+
+    #[test]
+    fn without_epoch() {
+        let info =
+            parse_srcinfo("pkgbase = bar\n\tpkgver = r12.abc\n\tpkgrel = 1\npkgname = bar\n");
+        assert_eq!(info.version, "r12.abc-1");
+        assert!(info.depends.is_empty());
+    }
+
+    #[test]
+    fn ignores_other_lines() {
+        let info = parse_srcinfo(
+            "# Generated by mksrcinfo\npkgbase = baz\n\tpkgver = 1\n\tpkgrel = 1\n\tpkgname=notakey\n\npkgname = baz\n",
+        );
+        assert_eq!(info.names, vec!["baz"]);
+        assert_eq!(info.version, "1-1");
+    }
+
+    #[test]
+    fn url_dir_names() {
+        assert_eq!(
+            url_dir_name("git+https://example.com/foo.git"),
+            "git%2Bhttps%3A%2F%2Fexample.com%2Ffoo.git"
+        );
+        assert_ne!(url_dir_name("https://a/b_c"), url_dir_name("https://a_b/c"));
+    }
+}
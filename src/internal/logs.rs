@@ -0,0 +1,60 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::crash;
+use crate::internal::exit_code::AppExitCode;
+
+const LOG_DIR: &str = ".local/share/ame/logs";
+
+/// Returns the directory holding a package base's build logs
+pub fn log_dir(base: &str) -> String {
+    format!("{}/{}/{}", env::var("HOME").unwrap(), LOG_DIR, base)
+}
+
+/// Returns the path of a package base's log for the install started at `timestamp`, creating its directory
+pub fn log_path(base: &str, timestamp: &str) -> String {
+    let dir = log_dir(base);
+    fs::create_dir_all(&dir).unwrap_or_else(|e| {
+        crash!(
+            AppExitCode::FailedCreatingPaths,
+            "Couldn't create path: {}: {}",
+            dir,
+            e
+        );
+    });
+
+    format!("{dir}/{timestamp}.log")
+}
+
+/// Returns the most recent log of a package base, if there is one
+pub fn latest_log(base: &str) -> Option<String> {
+    // Timestamps sort chronologically, so the last file name is the newest
+    fs::read_dir(log_dir(base))
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "log"))
+        .max()
+        .map(|path| path.display().to_string())
+}
+
+/// Returns the last `count` lines of a log
+pub fn tail(path: &str, count: usize) -> Vec<String> {
+    let mut buf = vec![];
+    if let Ok(mut file) = File::open(Path::new(path)) {
+        // Only read the end of the file, build logs can get large
+        let len = file.metadata().map_or(0, |m| m.len());
+        file.seek(SeekFrom::Start(len.saturating_sub(count as u64 * 512)))
+            .ok();
+        file.read_to_end(&mut buf).ok();
+    }
+
+    let text = String::from_utf8_lossy(&buf);
+    let lines = text.lines().collect::<Vec<&str>>();
+    lines[lines.len().saturating_sub(count)..]
+        .iter()
+        .map(|line| (*line).to_string())
+        .collect()
+}
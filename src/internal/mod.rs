@@ -7,19 +7,34 @@ pub use sort::*;
 pub use sort::*;
 pub use sudoloop::*;
 
+pub mod archive;
 mod clean;
 pub mod commands;
 pub mod config;
 mod detect;
 pub mod error;
 pub mod exit_code;
+pub mod foreign;
+pub mod history;
+pub mod hooks;
 mod initialise;
+pub mod local_repo;
+pub mod local_source;
+pub mod logs;
+pub mod news;
+pub mod pacman_conf;
+pub mod plan;
+pub mod query;
+pub mod rebuild;
 pub mod rpc;
+pub mod selection;
 mod sort;
+pub mod sources;
 pub mod structs;
 #[macro_use]
 pub mod utils;
 mod sudoloop;
+pub mod updates;
 
 #[macro_export]
 macro_rules! uwu {
@@ -0,0 +1,100 @@
+use chrono::{DateTime, FixedOffset};
+use regex::Regex;
+use std::fs;
+
+use crate::internal::config;
+use crate::internal::error::{AppError, AppResult};
+use crate::internal::rpc::agent;
+
+pub const DEFAULT_NEWS_URL: &str = "https://archlinux.org/feeds/news/";
+
+#[derive(Debug, Clone)]
+/// A single post from the news feed
+pub struct NewsPost {
+    pub title: String,
+    pub link: String,
+    pub date: DateTime<FixedOffset>,
+    pub description: String,
+}
+
+/// Reads the raw feed from the configured URL, which may also be a local file
+fn fetch_feed(url: &str) -> AppResult<String> {
+    if let Some(path) = url.strip_prefix("file://") {
+        return Ok(fs::read_to_string(path)?);
+    }
+    if url.starts_with('/') {
+        return Ok(fs::read_to_string(url)?);
+    }
+
+    agent()
+        .get(url)
+        .call()
+        .map_err(|e| AppError::from(e.to_string()))?
+        .into_string()
+        .map_err(AppError::from)
+}
+
+/// Decodes the handful of XML/HTML entities used in the feed
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+/// Reads a tag's text content from an RSS item, unwrapping CDATA sections
+fn tag(item: &str, name: &str) -> String {
+    let re = Regex::new(&format!(r"(?s)<{name}>(.*?)</{name}>")).unwrap();
+    let content = re
+        .captures(item)
+        .and_then(|c| c.get(1))
+        .map_or("", |m| m.as_str())
+        .trim();
+    let content = content
+        .strip_prefix("<![CDATA[")
+        .and_then(|c| c.strip_suffix("]]>"))
+        .unwrap_or(content);
+
+    unescape(content)
+}
+
+/// Turns an HTML post body into plain text
+fn strip_html(html: &str) -> String {
+    let paragraphs = Regex::new(r"(?i)</p>|<br\s*/?>").unwrap();
+    let tags = Regex::new(r"<[^>]*>").unwrap();
+    let blank_lines = Regex::new(r"\n\s*\n\s*").unwrap();
+
+    let text = paragraphs.replace_all(html, "\n\n");
+    let text = unescape(&tags.replace_all(&text, ""));
+
+    blank_lines.replace_all(text.trim(), "\n\n").to_string()
+}
+
+/// Fetches and parses the news feed, newest post first
+pub fn fetch_news() -> AppResult<Vec<NewsPost>> {
+    let url = config::read()
+        .base
+        .news_url
+        .unwrap_or_else(|| DEFAULT_NEWS_URL.to_string());
+    let feed = fetch_feed(&url)?;
+
+    let items = Regex::new(r"(?s)<item>(.*?)</item>").unwrap();
+    let mut posts = items
+        .captures_iter(&feed)
+        .filter_map(|item| {
+            let item = item.get(1)?.as_str();
+            Some(NewsPost {
+                title: tag(item, "title"),
+                link: tag(item, "link"),
+                date: DateTime::parse_from_rfc2822(&tag(item, "pubDate")).ok()?,
+                description: strip_html(&tag(item, "description")),
+            })
+        })
+        .collect::<Vec<NewsPost>>();
+    posts.sort_by_key(|post| std::cmp::Reverse(post.date));
+
+    Ok(posts)
+}
@@ -0,0 +1,282 @@
+use std::fs;
+use std::path::Path;
+
+use crate::internal::commands::ShellCommand;
+use crate::internal::config;
+use crate::internal::error::SilentUnwrap;
+use crate::internal::exit_code::AppExitCode;
+use crate::{crash, info};
+
+const PACMAN_CONF: &str = "/etc/pacman.conf";
+
+/// How deep `Include` directives are followed before giving up on a loop
+const MAX_INCLUDE_DEPTH: usize = 10;
+
+#[derive(Debug, Default)]
+/// The parts of pacman.conf amethyst cares about
+pub struct PacmanConf {
+    pub ignore_pkg: Vec<String>,
+    pub ignore_group: Vec<String>,
+    pub repos: Vec<String>,
+}
+
+impl PacmanConf {
+    /// Expands `IgnoreGroup` into the names of the installed packages in those groups
+    pub fn ignored_group_members(&self) -> Vec<String> {
+        if self.ignore_group.is_empty() {
+            return vec![];
+        }
+
+        ShellCommand::pacman()
+            .arg("-Qqg")
+            .args(["--color", "never"])
+            .args(&self.ignore_group)
+            .wait_with_output()
+            .silent_unwrap(AppExitCode::PacmanError)
+            .stdout
+            .lines()
+            .map(ToString::to_string)
+            .collect()
+    }
+}
+
+/// The packages left out of upgrades by pacman.conf's `IgnorePkg` and `IgnoreGroup` or amethyst's `ignore`
+pub struct Ignored {
+    pub patterns: Vec<String>,
+    pub group_members: Vec<String>,
+}
+
+impl Ignored {
+    /// Reads the ignored packages from pacman.conf and amethyst's config
+    pub fn read() -> Self {
+        let pacman_conf = read();
+        let group_members = pacman_conf.ignored_group_members();
+        let patterns = pacman_conf
+            .ignore_pkg
+            .into_iter()
+            .chain(config::read().base.ignore)
+            .collect();
+
+        Self {
+            patterns,
+            group_members,
+        }
+    }
+
+    /// Checks whether upgrades of a package are ignored
+    pub fn contains(&self, name: &str) -> bool {
+        self.group_members.iter().any(|member| member == name)
+            || self
+                .patterns
+                .iter()
+                .any(|pattern| glob_match(pattern, name))
+    }
+}
+
+/// Matches a name against a shell-style glob supporting `*` and `?`, as pacman does for `IgnorePkg`
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<char>>();
+    let name = name.chars().collect::<Vec<char>>();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, n));
+            p += 1;
+        } else if let Some((bp, bn)) = backtrack {
+            // Let the last `*` swallow one more character and retry
+            p = bp + 1;
+            n = bn + 1;
+            backtrack = Some((bp, bn + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Resolves an `Include` path, which may contain globs in its file name
+fn expand_include(path: &str) -> Vec<String> {
+    if !path.contains(['*', '?']) {
+        return vec![path.to_string()];
+    }
+
+    let path = Path::new(path);
+    let pattern = path.file_name().unwrap_or_default().to_string_lossy();
+    let mut files = fs::read_dir(path.parent().unwrap_or_else(|| Path::new("/")))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|f| glob_match(&pattern, &f.file_name().to_string_lossy()))
+        .map(|f| f.path().to_string_lossy().to_string())
+        .collect::<Vec<String>>();
+    files.sort();
+    files
+}
+
+fn parse_file(path: &str, section: &mut String, conf: &mut PacmanConf, depth: usize) {
+    if depth > MAX_INCLUDE_DEPTH {
+        crash!(
+            AppExitCode::ConfigParseError,
+            "Too many nested includes in pacman config, stopped at {}",
+            path
+        );
+    }
+
+    let contents = fs::read_to_string(path).unwrap_or_else(|e| {
+        crash!(
+            AppExitCode::ConfigParseError,
+            "Couldn't read {}: {}",
+            path,
+            e
+        );
+    });
+
+    for line in contents.lines() {
+        // Strip comments and surrounding whitespace
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if line.starts_with('[') && line.ends_with(']') {
+            *section = line[1..line.len() - 1].to_string();
+            if section.as_str() != "options" {
+                conf.repos.push(section.clone());
+            }
+            continue;
+        }
+
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => (line, ""),
+        };
+
+        match key {
+            "Include" => {
+                for include in expand_include(value) {
+                    parse_file(&include, section, conf, depth + 1);
+                }
+            }
+            "IgnorePkg" if section.as_str() == "options" => conf
+                .ignore_pkg
+                .extend(value.split_whitespace().map(ToString::to_string)),
+            "IgnoreGroup" if section.as_str() == "options" => conf
+                .ignore_group
+                .extend(value.split_whitespace().map(ToString::to_string)),
+            _ => {}
+        }
+    }
+}
+
+/// Parses /etc/pacman.conf, following any `Include` directives
+pub fn read() -> PacmanConf {
+    let mut conf = PacmanConf::default();
+    parse_file(PACMAN_CONF, &mut String::new(), &mut conf, 0);
+
+    conf
+}
+
+/// Adds a package to the `IgnorePkg` list in pacman.conf, creating the entry if needed
+pub fn add_ignore_pkg(pkg: &str) {
+    let conf = fs::read_to_string(PACMAN_CONF).unwrap_or_else(|e| {
+        crash!(
+            AppExitCode::ConfigParseError,
+            "Couldn't read {}: {}",
+            PACMAN_CONF,
+            e
+        );
+    });
+
+    // Append to the first active `IgnorePkg` line, or add one right below `[options]`
+    let script = if conf
+        .lines()
+        .any(|line| line.trim_start().starts_with("IgnorePkg"))
+    {
+        format!("0,/^\\s*IgnorePkg\\s*=/s/^\\(\\s*IgnorePkg\\s*=.*\\)$/\\1 {pkg}/")
+    } else {
+        format!("/^\\[options\\]/a IgnorePkg = {pkg}")
+    };
+
+    ShellCommand::sed()
+        .elevated()
+        .args(["-i", &script, PACMAN_CONF])
+        .wait_success()
+        .silent_unwrap(AppExitCode::Other);
+
+    info!("Added {} to IgnorePkg in {}", pkg, PACMAN_CONF);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_literal() {
+        assert!(glob_match("linux", "linux"));
+        assert!(!glob_match("linux", "linux-lts"));
+        assert!(!glob_match("linux-lts", "linux"));
+    }
+
+    #[test]
+    fn glob_star() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("linux*", "linux"));
+        assert!(glob_match("linux*", "linux-headers"));
+        assert!(glob_match("*-git", "foo-git"));
+        assert!(!glob_match("*-git", "foo-git-bin"));
+    }
+
+    #[test]
+    fn glob_question_mark() {
+        assert!(glob_match("python?", "python3"));
+        assert!(!glob_match("python?", "python"));
+        assert!(!glob_match("python?", "python31"));
+    }
+
+    #[test]
+    fn glob_backtracking() {
+        assert!(glob_match("*-*-git", "foo-bar-baz-git"));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+        assert!(!glob_match("a*b*c", "aXbYbZ"));
+        assert!(glob_match("*a?", "bananas"));
+        assert!(!glob_match("*a?", "banana"));
+    }
+
+    fn parse(name: &str, contents: &str) -> PacmanConf {
+        let path = std::env::temp_dir().join(format!("ame-test-{}-{}", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+
+        let mut conf = PacmanConf::default();
+        parse_file(&path.to_string_lossy(), &mut String::new(), &mut conf, 0);
+        fs::remove_file(&path).ok();
+
+        conf
+    }
+
+    #[test]
+    fn ignores_only_options() {
+        let conf = parse(
+            "options.conf",
+            "[options]\nIgnorePkg = foo bar\nIgnoreGroup = gnome\n\n[core]\nIgnorePkg = baz\nIgnoreGroup = kde\n",
+        );
+        assert_eq!(conf.ignore_pkg, vec!["foo", "bar"]);
+        assert_eq!(conf.ignore_group, vec!["gnome"]);
+        assert_eq!(conf.repos, vec!["core"]);
+    }
+
+    #[test]
+    fn inline_comments() {
+        let conf = parse(
+            "comments.conf",
+            "# IgnorePkg = commented\n[options] # main section\nIgnorePkg = foo # bar\n#IgnorePkg = baz\n[extra]#repo\n",
+        );
+        assert_eq!(conf.ignore_pkg, vec!["foo"]);
+        assert_eq!(conf.repos, vec!["extra"]);
+    }
+}
@@ -0,0 +1,476 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::path::Path;
+use std::process::Command;
+use std::{env, fs};
+
+use crate::internal::config;
+use crate::internal::dep_name;
+use crate::internal::error::{AppError, AppResult};
+use crate::internal::exit_code::AppExitCode;
+use crate::internal::local_source::LocalSource;
+use crate::internal::rpc::rpcinfo;
+use crate::internal::sort;
+use crate::internal::sources;
+use crate::{crash, log, warn, Options};
+
+/// Points at the build directory of the last install that didn't finish
+const RESUME_FILE: &str = ".local/share/ame/resume";
+/// Where an install's state is kept inside its build directory
+const STATE_FILE: &str = "ame-install.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+/// An AUR package base to build, along with what it needs
+pub struct PlannedBuild {
+    pub base: String,
+    pub version: String,
+    /// The packages from this base that should be installed
+    pub packages: Vec<String>,
+    /// Whether any of the packages was asked for, rather than pulled in as a dependency
+    pub explicit: bool,
+    /// The AUR package bases that must be built and installed first
+    pub aur_deps: Vec<String>,
+    pub out_of_date: Option<usize>,
+    /// The directory holding the PKGBUILD, when it doesn't come from the AUR
+    #[serde(default)]
+    pub source: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+/// Everything that has to happen to install a set of AUR packages
+pub struct Plan {
+    /// Package bases to build, dependencies before their dependents
+    pub builds: Vec<PlannedBuild>,
+    /// Missing dependencies that can be installed from the repos
+    pub repo_deps: Vec<String>,
+    /// Dependencies only needed to run `check()`, removed again once everything is built
+    #[serde(default)]
+    pub check_deps: Vec<String>,
+    /// Dependencies only needed to build, which can be removed once everything is built
+    #[serde(default)]
+    pub make_deps: Vec<String>,
+}
+
+/// A planned package whose dependencies still have to be resolved
+struct Unresolved {
+    name: String,
+    base: String,
+    deps: Vec<String>,
+    make_deps: Vec<String>,
+    check_deps: Vec<String>,
+}
+
+/// Returns the dependencies that aren't satisfied by any installed package, using `pacman -T`
+fn unsatisfied(deps: &[String]) -> Vec<String> {
+    if deps.is_empty() {
+        return vec![];
+    }
+
+    let output = Command::new("pacman")
+        .arg("-T")
+        .args(deps)
+        .output()
+        .unwrap_or_else(|e| {
+            crash!(
+                AppExitCode::PacmanError,
+                "Couldn't check dependencies: {}",
+                e
+            );
+        });
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(ToString::to_string)
+        .collect()
+}
+
+/// Plans the build of a PKGBUILD from outside the AUR, returning its dependencies to resolve
+fn plan_local(
+    source: &LocalSource,
+    explicit: bool,
+    nocheck: bool,
+    builds: &mut BTreeMap<String, PlannedBuild>,
+    bases: &mut BTreeMap<String, String>,
+) -> Unresolved {
+    let info = &source.info;
+    for name in &info.names {
+        bases.insert(name.clone(), info.base.clone());
+    }
+    builds.insert(
+        info.base.clone(),
+        PlannedBuild {
+            base: info.base.clone(),
+            version: info.version.clone(),
+            packages: info.names.clone(),
+            explicit,
+            aur_deps: vec![],
+            out_of_date: None,
+            source: Some(source.dir.clone()),
+        },
+    );
+    Unresolved {
+        name: info.base.clone(),
+        base: info.base.clone(),
+        deps: info.depends.clone(),
+        make_deps: info.make_depends.clone(),
+        check_deps: if nocheck {
+            vec![]
+        } else {
+            info.check_depends.clone()
+        },
+    }
+}
+
+impl Plan {
+    /// Resolves AUR packages, PKGBUILDs from elsewhere and all of their missing dependencies into a build plan
+    pub fn resolve(targets: &[String], local: &[LocalSource], options: Options) -> Self {
+        let verbosity = options.verbosity;
+        let mut builds: BTreeMap<String, PlannedBuild> = BTreeMap::new();
+        let mut bases: BTreeMap<String, String> = BTreeMap::new();
+        let mut dep_names: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        let mut repo_deps: Vec<String> = vec![];
+        let config = config::read();
+        let skips_check = |base: &str, names: &[String]| {
+            options.build.nocheck || config.package_settings(base, names).nocheck()
+        };
+
+        // Dependencies only pulled in for check(), and the ones something else needs too
+        let mut check_only: Vec<String> = vec![];
+        let mut make_only: Vec<String> = vec![];
+        let mut needed: Vec<String> = vec![];
+
+        // Local PKGBUILDs are always built, and satisfy dependencies on their packages
+        let mut queue: VecDeque<(String, bool)> = VecDeque::new();
+        let mut pending: Vec<Unresolved> = vec![];
+        for source in local {
+            let nocheck = skips_check(&source.info.base, &source.info.names);
+            pending.push(plan_local(source, true, nocheck, &mut builds, &mut bases));
+        }
+        let mut local_names = local
+            .iter()
+            .flat_map(|s| s.info.names.clone())
+            .collect::<Vec<String>>();
+        queue.extend(targets.iter().map(|t| (t.clone(), true)));
+
+        loop {
+            // Work through the dependencies of whatever was planned last
+            for item in std::mem::take(&mut pending) {
+                let Unresolved {
+                    name,
+                    base,
+                    deps,
+                    make_deps,
+                    check_deps,
+                } = item;
+                let names = |deps: &[String]| {
+                    deps.iter()
+                        .map(|d| dep_name(d).to_string())
+                        .collect::<Vec<String>>()
+                };
+                let (runtime_names, make_names) = (names(&deps), names(&make_deps));
+
+                let (local_deps, deps): (Vec<String>, Vec<String>) = deps
+                    .into_iter()
+                    .chain(make_deps)
+                    .chain(check_deps)
+                    .partition(|dep| local_names.iter().any(|n| n == dep_name(dep)));
+                for dep in local_deps {
+                    dep_names
+                        .entry(base.clone())
+                        .or_default()
+                        .push(dep_name(&dep).to_string());
+                }
+
+                // Sort dependencies, makedepends and checkdepends that aren't installed yet
+                let sorted = sort(&unsatisfied(&deps), options);
+                if verbosity >= 1 {
+                    log!(
+                        "Sorted missing dependencies for {} are:\n{:?}",
+                        name,
+                        &sorted
+                    );
+                }
+
+                // If any dependencies are not found in AUR or repos, crash
+                if !sorted.nf.is_empty() {
+                    crash!(
+                        AppExitCode::MissingDeps,
+                        "Could not find dependencies {} for package {}, aborting",
+                        sorted.nf.join(", "),
+                        name,
+                    );
+                }
+
+                // Remember what is only needed for building, so it can be removed afterwards
+                for dep in sorted.repo.iter().chain(&sorted.aur).chain(&sorted.custom) {
+                    if runtime_names.contains(dep) {
+                        needed.push(dep.clone());
+                    } else if make_names.contains(dep) {
+                        make_only.push(dep.clone());
+                    } else {
+                        check_only.push(dep.clone());
+                    }
+                }
+
+                for dep in sorted.repo {
+                    if !repo_deps.contains(&dep) {
+                        repo_deps.push(dep);
+                    }
+                }
+                for dep in sorted.aur {
+                    dep_names.entry(base.clone()).or_default().push(dep.clone());
+                    queue.push_back((dep, false));
+                }
+                // Dependencies from custom sources are planned right away, like local PKGBUILDs
+                for dep in sorted.custom {
+                    dep_names.entry(base.clone()).or_default().push(dep.clone());
+                    if bases.contains_key(&dep) {
+                        continue;
+                    }
+                    let source = sources::find_any(&dep).unwrap_or_else(|| {
+                        crash!(
+                            AppExitCode::MissingDeps,
+                            "Could not find {} in any source, aborting",
+                            dep
+                        );
+                    });
+                    local_names.extend(source.info.names.iter().cloned());
+                    let nocheck = skips_check(&source.info.base, &source.info.names);
+                    pending.push(plan_local(&source, false, nocheck, &mut builds, &mut bases));
+                }
+            }
+
+            let Some((name, explicit)) = queue.pop_front() else {
+                break;
+            };
+
+            // Already planned, possibly as part of the same package base
+            if let Some(base) = bases.get(&name) {
+                builds.get_mut(base).unwrap().explicit |= explicit;
+                continue;
+            }
+
+            // Query AUR for package info
+            let rpcres = rpcinfo(&name);
+            let package = rpcres.package.unwrap_or_else(|| {
+                crash!(
+                    AppExitCode::MissingDeps,
+                    "Could not find {} in the AUR, aborting",
+                    name
+                );
+            });
+            let base = package.base.clone().unwrap_or_else(|| name.clone());
+            bases.insert(name.clone(), base.clone());
+
+            let build = builds.entry(base.clone()).or_insert_with(|| PlannedBuild {
+                base: base.clone(),
+                version: package.version.clone(),
+                packages: vec![],
+                explicit: false,
+                aur_deps: vec![],
+                out_of_date: package.out_of_date,
+                source: None,
+            });
+            build.packages.push(name.clone());
+            build.explicit |= explicit;
+
+            let check_deps = if skips_check(&base, std::slice::from_ref(&name)) {
+                vec![]
+            } else {
+                package.check_depends.clone()
+            };
+            pending.push(Unresolved {
+                name,
+                base,
+                deps: package.depends.clone(),
+                make_deps: package.make_depends.clone(),
+                check_deps,
+            });
+        }
+
+        // Now that every package is known, point dependencies at package bases
+        for (base, names) in dep_names {
+            let mut deps = names
+                .iter()
+                .filter_map(|name| bases.get(name))
+                .filter(|dep| **dep != base)
+                .cloned()
+                .collect::<Vec<String>>();
+            deps.sort();
+            deps.dedup();
+            builds.get_mut(&base).unwrap().aur_deps = deps;
+        }
+
+        // Anything asked for or needed at runtime stays, and makedepends outlive checkdepends
+        let explicit = builds
+            .values()
+            .filter(|b| b.explicit)
+            .flat_map(|b| b.packages.clone())
+            .collect::<Vec<String>>();
+        needed.extend(explicit);
+        make_only.retain(|dep| !needed.contains(dep));
+        make_only.sort();
+        make_only.dedup();
+        check_only.retain(|dep| !needed.contains(dep) && !make_only.contains(dep));
+        check_only.sort();
+        check_only.dedup();
+
+        let plan = Self {
+            builds: order(builds),
+            repo_deps,
+            check_deps: check_only,
+            make_deps: make_only,
+        };
+        if verbosity >= 1 {
+            log!("Build plan: {:?}", &plan);
+        }
+
+        plan
+    }
+}
+
+/// Orders builds so every package base comes after the ones it depends on
+fn order(mut remaining: BTreeMap<String, PlannedBuild>) -> Vec<PlannedBuild> {
+    let mut ordered: Vec<PlannedBuild> = vec![];
+
+    while !remaining.is_empty() {
+        let ready = remaining
+            .values()
+            .filter(|build| {
+                build
+                    .aur_deps
+                    .iter()
+                    .all(|dep| ordered.iter().any(|b| &b.base == dep))
+            })
+            .map(|build| build.base.clone())
+            .collect::<Vec<String>>();
+
+        if ready.is_empty() {
+            crash!(
+                AppExitCode::MissingDeps,
+                "Dependency cycle between AUR packages {}, aborting",
+                remaining
+                    .keys()
+                    .cloned()
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            );
+        }
+
+        for base in ready {
+            ordered.push(remaining.remove(&base).unwrap());
+        }
+    }
+
+    ordered
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+/// How far a package base got during an install
+pub enum Step {
+    Cloned,
+    Reviewed,
+    Fetched,
+    Built,
+    /// Installed, or copied to the output directory when only building
+    Installed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+/// An AUR install in progress, saved after every step so an interrupted install can be resumed
+pub struct InstallState {
+    pub targets: Vec<String>,
+    pub plan: Plan,
+    pub cachedir: String,
+    /// Whether the build directory was made for this install and should be removed afterwards
+    pub temporary: bool,
+    /// When the install started, which also names its build logs
+    pub timestamp: String,
+    /// Where to put the packages of the targets instead of installing them, when only building
+    #[serde(default)]
+    pub output: Option<String>,
+    pub steps: BTreeMap<String, Step>,
+}
+
+fn resume_path() -> String {
+    format!("{}/{}", env::var("HOME").unwrap(), RESUME_FILE)
+}
+
+impl InstallState {
+    /// Starts tracking a new install, making it the one `--resume` picks up
+    pub fn new(
+        targets: &[String],
+        plan: Plan,
+        cachedir: &str,
+        temporary: bool,
+        timestamp: &str,
+        output: Option<String>,
+    ) -> Self {
+        let state = Self {
+            targets: targets.to_vec(),
+            plan,
+            cachedir: cachedir.to_string(),
+            temporary,
+            timestamp: timestamp.to_string(),
+            output,
+            steps: BTreeMap::new(),
+        };
+        state.save();
+
+        state
+    }
+
+    /// Loads the last install that didn't finish, if there is one
+    pub fn load_last() -> Option<Self> {
+        let cachedir = fs::read_to_string(resume_path()).ok()?;
+        let state = fs::read_to_string(format!("{}/{}", cachedir.trim(), STATE_FILE)).ok()?;
+
+        serde_json::from_str(&state).ok()
+    }
+
+    /// Returns whether a package base got at least as far as `step`
+    pub fn reached(&self, base: &str, step: Step) -> bool {
+        self.steps.get(base).is_some_and(|s| *s >= step)
+    }
+
+    /// Records that a package base finished a step
+    pub fn complete(&mut self, base: &str, step: Step) {
+        self.steps.insert(base.to_string(), step);
+        self.save();
+    }
+
+    /// Points the install at a build directory that was moved
+    pub fn moved(&mut self, cachedir: &str) {
+        self.cachedir = cachedir.to_string();
+        self.save();
+    }
+
+    /// Forgets about the install once it's done, so it can't be resumed anymore
+    pub fn finish(&self) {
+        fs::remove_file(format!("{}/{}", self.cachedir, STATE_FILE)).ok();
+        if fs::read_to_string(resume_path()).is_ok_and(|dir| dir.trim() == self.cachedir) {
+            fs::remove_file(resume_path()).ok();
+        }
+    }
+
+    fn save(&self) {
+        // Losing the state only means the install can't be resumed, so don't stop for it
+        if let Err(e) = self.write() {
+            warn!("Couldn't save the install state: {}", e);
+        }
+    }
+
+    fn write(&self) -> AppResult<()> {
+        let state = serde_json::to_string(self).map_err(|e| AppError::from(e.to_string()))?;
+        fs::write(format!("{}/{}", self.cachedir, STATE_FILE), state)?;
+
+        let resume = resume_path();
+        if let Some(dir) = Path::new(&resume).parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(resume, &self.cachedir)?;
+
+        Ok(())
+    }
+}
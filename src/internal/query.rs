@@ -0,0 +1,77 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::internal::commands::ShellCommand;
+use crate::internal::config;
+use crate::internal::error::SilentUnwrap;
+use crate::internal::exit_code::AppExitCode;
+
+/// Returns all installed packages and their versions
+pub fn installed() -> BTreeMap<String, String> {
+    ShellCommand::pacman()
+        .arg("-Q")
+        .args(["--color", "never"])
+        .wait_with_output()
+        .silent_unwrap(AppExitCode::PacmanError)
+        .stdout
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .map(|(name, version)| (name.to_string(), version.to_string()))
+        .collect()
+}
+
+/// Returns the names of all installed foreign packages, including those installed from the local repo
+pub fn foreign() -> BTreeSet<String> {
+    let mut foreign = ShellCommand::pacman()
+        .arg("-Qqm")
+        .args(["--color", "never"])
+        .wait_with_output()
+        .silent_unwrap(AppExitCode::PacmanError)
+        .stdout
+        .lines()
+        .map(ToString::to_string)
+        .collect::<BTreeSet<String>>();
+    foreign.extend(local_repo_packages());
+
+    foreign
+}
+
+/// Returns the names of all packages in the local repo. Pacman doesn't count those as foreign,
+/// as they are installed from a sync repo, even though they were built from the AUR.
+pub fn local_repo_packages() -> Vec<String> {
+    let Some(repo) = config::read().local_repo else {
+        return vec![];
+    };
+
+    // Fails if the repo isn't in pacman.conf yet, in which case nothing was installed from it
+    let output = ShellCommand::pacman()
+        .arg("-Slq")
+        .args(["--color", "never"])
+        .arg(&repo.name)
+        .wait_with_output()
+        .silent_unwrap(AppExitCode::PacmanError);
+    if !output.status.success() {
+        return vec![];
+    }
+
+    output.stdout.lines().map(ToString::to_string).collect()
+}
+
+/// Returns the installed version of a package, if it is installed
+pub fn installed_version(pkg: &str) -> Option<String> {
+    let output = ShellCommand::pacman()
+        .arg("-Q")
+        .args(["--color", "never"])
+        .arg(pkg)
+        .wait_with_output()
+        .silent_unwrap(AppExitCode::PacmanError);
+
+    if output.status.success() {
+        output
+            .stdout
+            .split_whitespace()
+            .nth(1)
+            .map(ToString::to_string)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,180 @@
+use regex::Regex;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::process::Command;
+
+use crate::internal::commands::ShellCommand;
+use crate::internal::error::SilentUnwrap;
+use crate::internal::exit_code::AppExitCode;
+use crate::internal::foreign::QueriedPackage;
+use crate::{log, Options};
+
+/// Directories the dynamic linker always searches
+const DEFAULT_LIB_DIRS: [&str; 3] = ["/usr/lib", "/usr/lib32", "/lib"];
+
+#[derive(Debug)]
+/// A foreign package that needs to be rebuilt, and why
+pub struct BrokenPackage {
+    pub name: String,
+    pub reasons: Vec<String>,
+}
+
+/// Returns all sonames known to the dynamic linker's cache
+fn known_sonames() -> HashSet<String> {
+    let output = Command::new("ldconfig").arg("-p").output();
+    output.map_or_else(
+        |_| HashSet::new(),
+        |output| {
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .filter_map(|line| line.split_whitespace().next())
+                .map(ToString::to_string)
+                .collect()
+        },
+    )
+}
+
+/// Returns the `pythonX.Y` directory name of the installed Python 3, if any
+fn python_version() -> Option<String> {
+    let output = Command::new("python3")
+        .args([
+            "-c",
+            "import sys; print(f'python{sys.version_info[0]}.{sys.version_info[1]}')",
+        ])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Lists the regular files owned by a package
+fn package_files(pkg: &str) -> Vec<String> {
+    ShellCommand::pacman()
+        .args(["-Qlq", "--color", "never"])
+        .arg(pkg)
+        .wait_with_output()
+        .silent_unwrap(AppExitCode::PacmanError)
+        .stdout
+        .lines()
+        .filter(|file| !file.ends_with('/'))
+        .map(ToString::to_string)
+        .collect()
+}
+
+/// Checks the ELF magic number of a file
+fn is_elf(path: &str) -> bool {
+    let mut magic = [0; 4];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok()
+        && magic == *b"\x7fELF"
+}
+
+/// Returns the shared libraries an ELF file needs that can't be found
+fn missing_libraries(path: &str, known: &HashSet<String>) -> Vec<String> {
+    let output = match Command::new("readelf").args(["-d", "-W", path]).output() {
+        Ok(output) if output.status.success() => output,
+        _ => return vec![],
+    };
+    let dynamic = String::from_utf8_lossy(&output.stdout);
+
+    let needed_re = Regex::new(r"\(NEEDED\)\s+Shared library: \[(.+)\]").unwrap();
+    let runpath_re = Regex::new(r"\((?:RUNPATH|RPATH)\)\s+Library r(?:un)?path: \[(.+)\]").unwrap();
+
+    // Expand $ORIGIN in RUNPATH/RPATH entries relative to the file's directory
+    let origin = Path::new(path)
+        .parent()
+        .map_or_else(String::new, |p| p.display().to_string());
+    let search_dirs = runpath_re
+        .captures_iter(&dynamic)
+        .flat_map(|c| {
+            c[1].split(':')
+                .map(|dir| {
+                    dir.replace("$ORIGIN", &origin)
+                        .replace("${ORIGIN}", &origin)
+                })
+                .collect::<Vec<String>>()
+        })
+        .chain(DEFAULT_LIB_DIRS.iter().map(ToString::to_string))
+        .collect::<Vec<String>>();
+
+    needed_re
+        .captures_iter(&dynamic)
+        .map(|c| c[1].to_string())
+        .filter(|lib| {
+            !known.contains(lib)
+                && !search_dirs
+                    .iter()
+                    .any(|dir| Path::new(&format!("{dir}/{lib}")).exists())
+        })
+        .collect()
+}
+
+/// Scans foreign packages for unresolved shared libraries and files in stale Python paths
+pub fn find_broken(foreign: &[QueriedPackage], options: Options) -> Vec<BrokenPackage> {
+    let verbosity = options.verbosity;
+    let known = known_sonames();
+    let python = python_version();
+    let site_packages = Regex::new(r"^/usr/lib/(python3\.\d+)/site-packages/").unwrap();
+
+    if verbosity >= 1 {
+        log!(
+            "{} sonames known to the linker, current Python is {:?}",
+            known.len(),
+            python
+        );
+    }
+
+    foreign
+        .iter()
+        .filter_map(|pkg| {
+            let files = package_files(&pkg.name);
+            let mut reasons = vec![];
+
+            // Files installed for a Python version that is no longer current
+            if let Some(python) = &python {
+                let mut stale = files
+                    .iter()
+                    .filter_map(|file| site_packages.captures(file))
+                    .map(|c| c[1].to_string())
+                    .filter(|version| version != python)
+                    .collect::<Vec<String>>();
+                stale.sort();
+                stale.dedup();
+                reasons.extend(
+                    stale
+                        .into_iter()
+                        .map(|version| format!("installed for {version} site-packages")),
+                );
+            }
+
+            // Binaries and libraries linked against sonames that are gone
+            let mut missing = files
+                .iter()
+                .filter(|file| is_elf(file))
+                .flat_map(|file| missing_libraries(file, &known))
+                .collect::<Vec<String>>();
+            missing.sort();
+            missing.dedup();
+            reasons.extend(missing.into_iter().map(|lib| format!("missing {lib}")));
+
+            if verbosity >= 1 {
+                log!("{}: {:?}", pkg.name, &reasons);
+            }
+
+            if reasons.is_empty() {
+                None
+            } else {
+                Some(BrokenPackage {
+                    name: pkg.name.clone(),
+                    reasons,
+                })
+            }
+        })
+        .collect()
+}
@@ -1,10 +1,20 @@
-use std::sync::Arc;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use crate::crash;
+use crate::internal::error::{AppError, AppResult};
+use crate::internal::exit_code::AppExitCode;
 
 #[derive(serde::Deserialize, Debug, Clone)]
 /// Struct for deserializing RPC results.
 pub struct Package {
     #[serde(rename = "Name")]
     pub name: String,
+    #[serde(rename = "PackageBase")]
+    #[serde(default)]
+    pub base: Option<String>,
     #[serde(rename = "Version")]
     pub version: String,
     #[serde(rename = "Description")]
@@ -15,12 +25,18 @@ pub struct Package {
     #[serde(rename = "MakeDepends")]
     #[serde(default)]
     pub make_depends: Vec<String>,
+    #[serde(rename = "CheckDepends")]
+    #[serde(default)]
+    pub check_depends: Vec<String>,
     #[serde(rename = "OptDepends")]
     #[serde(default)]
     pub opt_depends: Vec<String>,
     #[serde(rename = "OutOfDate")]
     #[serde(default)]
     pub out_of_date: Option<usize>,
+    #[serde(rename = "LastModified")]
+    #[serde(default)]
+    pub last_modified: Option<i64>,
 }
 
 #[derive(serde::Deserialize)]
@@ -39,59 +55,139 @@ pub struct InfoResults {
 
 pub const URL: &str = "https://aur.archlinux.org/";
 
-/// Return a struct of type [`InfoResults`] from the AUR.
-pub fn rpcinfo(pkg: &str) -> InfoResults {
-    // Initialise TLS connector
-    let tls_connector = Arc::new(native_tls::TlsConnector::new().unwrap());
+/// How long a single web request may take before giving up
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// The package field an AUR search matches against
+pub enum SearchBy {
+    Name,
+    #[default]
+    NameDesc,
+    Maintainer,
+    Depends,
+    MakeDepends,
+    OptDepends,
+    CheckDepends,
+    Provides,
+    Conflicts,
+    Replaces,
+    Keywords,
+    Groups,
+    Submitter,
+    Comaintainers,
+}
+
+impl Display for SearchBy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let by = match self {
+            Self::Name => "name",
+            Self::NameDesc => "name-desc",
+            Self::Maintainer => "maintainer",
+            Self::Depends => "depends",
+            Self::MakeDepends => "makedepends",
+            Self::OptDepends => "optdepends",
+            Self::CheckDepends => "checkdepends",
+            Self::Provides => "provides",
+            Self::Conflicts => "conflicts",
+            Self::Replaces => "replaces",
+            Self::Keywords => "keywords",
+            Self::Groups => "groups",
+            Self::Submitter => "submitter",
+            Self::Comaintainers => "comaintainers",
+        };
+        Display::fmt(by, f)
+    }
+}
+
+impl FromStr for SearchBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "name" => Ok(Self::Name),
+            "name-desc" => Ok(Self::NameDesc),
+            "maintainer" => Ok(Self::Maintainer),
+            "depends" => Ok(Self::Depends),
+            "makedepends" => Ok(Self::MakeDepends),
+            "optdepends" => Ok(Self::OptDepends),
+            "checkdepends" => Ok(Self::CheckDepends),
+            "provides" => Ok(Self::Provides),
+            "conflicts" => Ok(Self::Conflicts),
+            "replaces" => Ok(Self::Replaces),
+            "keywords" => Ok(Self::Keywords),
+            "groups" => Ok(Self::Groups),
+            "submitter" => Ok(Self::Submitter),
+            "comaintainers" => Ok(Self::Comaintainers),
+            _ => Err(format!(
+                "{s} is not one of name, name-desc, maintainer, depends, makedepends, optdepends, checkdepends, provides, conflicts, replaces, keywords, groups, submitter or comaintainers"
+            )),
+        }
+    }
+}
 
-    // Build request agent
-    let agent = ureq::AgentBuilder::new()
-        .tls_connector(tls_connector)
-        .build();
+/// Returns the request agent shared by every web request, so connections are reused between them
+pub fn agent() -> &'static ureq::Agent {
+    static AGENT: OnceLock<ureq::Agent> = OnceLock::new();
 
+    AGENT.get_or_init(|| {
+        // Initialise TLS connector
+        let tls_connector = Arc::new(native_tls::TlsConnector::new().unwrap());
+
+        // Build request agent
+        ureq::AgentBuilder::new()
+            .tls_connector(tls_connector)
+            .timeout(TIMEOUT)
+            .build()
+    })
+}
+
+/// Return a struct of type [`InfoResults`] from the AUR, crashing if the AUR can't be reached.
+pub fn rpcinfo(pkg: &str) -> InfoResults {
+    try_rpcinfo(pkg).unwrap_or_else(|e| {
+        crash!(AppExitCode::RpcError, "Couldn't query the AUR: {}", e);
+    })
+}
+
+/// Return a struct of type [`InfoResults`] from the AUR, or the error that kept it from arriving.
+pub fn try_rpcinfo(pkg: &str) -> AppResult<InfoResults> {
     // Send request and parse results into json
-    let res: SearchResults = agent
-        .get(&format!(
-            "https://aur.archlinux.org/rpc/?v=5&type=info&arg={}",
-            pkg
-        ))
+    let res: SearchResults = agent()
+        .get("https://aur.archlinux.org/rpc/")
+        .query("v", "5")
+        .query("type", "info")
+        .query("arg", pkg)
         .call()
-        .unwrap()
-        .into_json()
-        .unwrap();
+        .map_err(|e| AppError::from(e.to_string()))?
+        .into_json()?;
 
     // Check if package was found
     if res.results.is_empty() {
-        InfoResults {
+        Ok(InfoResults {
             found: false,
             package: None,
-        }
+        })
     } else {
-        InfoResults {
+        Ok(InfoResults {
             found: true,
             package: Some(res.results[0].clone()),
-        }
+        })
     }
 }
 
-/// Return a struct of type [`SearchResults`] from the AUR.
-pub fn rpcsearch(pkg: &str) -> SearchResults {
-    // Initialise TLS connector
-    let tls_connector = Arc::new(native_tls::TlsConnector::new().unwrap());
-
-    // Build request agent
-    let agent = ureq::AgentBuilder::new()
-        .tls_connector(tls_connector)
-        .build();
-
-    // Send request and parse results into json
-    agent
-        .get(&format!(
-            "https://aur.archlinux.org/rpc/?v=5&type=search&arg={}",
-            pkg
-        ))
+/// Return a struct of type [`SearchResults`] from the AUR, searching the given field.
+pub fn rpcsearch_by(pkg: &str, by: SearchBy) -> SearchResults {
+    // Send request and parse results into json, the query escapes characters like `+`
+    agent()
+        .get("https://aur.archlinux.org/rpc/")
+        .query("v", "5")
+        .query("type", "search")
+        .query("by", &by.to_string())
+        .query("arg", pkg)
         .call()
-        .unwrap()
-        .into_json::<SearchResults>()
-        .unwrap()
+        .map_err(|e| AppError::from(e.to_string()))
+        .and_then(|res| Ok(res.into_json::<SearchResults>()?))
+        .unwrap_or_else(|e| {
+            crash!(AppExitCode::RpcError, "Couldn't search the AUR: {}", e);
+        })
 }
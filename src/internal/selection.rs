@@ -0,0 +1,98 @@
+/// Parses a single entry number or range like `3` or `3-5` into zero-based indices
+fn parse_range(token: &str, len: usize) -> Result<Vec<usize>, String> {
+    let (start, end) = token.split_once('-').unwrap_or((token, token));
+    let start = start
+        .parse::<usize>()
+        .map_err(|_| format!("{token} is not a number or range"))?;
+    let end = end
+        .parse::<usize>()
+        .map_err(|_| format!("{token} is not a number or range"))?;
+
+    if start == 0 || end > len || start > end {
+        return Err(format!("{token} is out of range (1-{len})"));
+    }
+
+    Ok((start - 1..end).collect())
+}
+
+/// Parses an upgrade selection such as `1 3-5` or `^2` for a list of `len` entries, like yay's
+/// upgrade menu. Plain numbers and ranges exclude entries, `^` keeps only the given entries.
+/// Returns which entries to upgrade.
+pub fn parse_selection(input: &str, len: usize) -> Result<Vec<bool>, String> {
+    let tokens = input
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|t| !t.is_empty())
+        .collect::<Vec<&str>>();
+
+    let has_only = tokens.iter().any(|t| t.starts_with('^'));
+    let mut selected = vec![!has_only; len];
+
+    // Apply `^` before exclusions, so an excluded entry stays excluded regardless of order
+    for token in tokens.iter().filter_map(|t| t.strip_prefix('^')) {
+        for i in parse_range(token, len)? {
+            selected[i] = true;
+        }
+    }
+    for token in tokens.iter().filter(|t| !t.starts_with('^')) {
+        for i in parse_range(token, len)? {
+            selected[i] = false;
+        }
+    }
+
+    Ok(selected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exclusions() {
+        assert_eq!(parse_selection("2", 4), Ok(vec![true, false, true, true]));
+        assert_eq!(
+            parse_selection("1 3-4", 5),
+            Ok(vec![false, true, false, false, true])
+        );
+    }
+
+    #[test]
+    fn only() {
+        assert_eq!(parse_selection("^2", 3), Ok(vec![false, true, false]));
+        assert_eq!(
+            parse_selection("^1-3 ^5", 5),
+            Ok(vec![true, true, true, false, true])
+        );
+    }
+
+    #[test]
+    fn only_and_exclusions() {
+        assert_eq!(
+            parse_selection("2 ^1-3", 4),
+            Ok(vec![true, false, true, false])
+        );
+    }
+
+    #[test]
+    fn separators() {
+        assert_eq!(
+            parse_selection("1,3, 4", 5),
+            Ok(vec![false, true, false, false, true])
+        );
+        assert_eq!(parse_selection(" , ", 2), Ok(vec![true, true]));
+    }
+
+    #[test]
+    fn out_of_range() {
+        assert!(parse_selection("0", 3).is_err());
+        assert!(parse_selection("4", 3).is_err());
+        assert!(parse_selection("^2-4", 3).is_err());
+        assert!(parse_selection("3-1", 3).is_err());
+    }
+
+    #[test]
+    fn not_a_number() {
+        assert!(parse_selection("foo", 3).is_err());
+        assert!(parse_selection("^", 3).is_err());
+        assert!(parse_selection("1-", 3).is_err());
+    }
+}
@@ -1,6 +1,6 @@
 use std::process::{Command, Stdio};
 
-use crate::internal::{clean, rpc, structs};
+use crate::internal::{clean, rpc, sources, structs};
 use crate::{log, Options};
 
 /// Sorts the given packages into an [`crate::internal::structs::Sorted`]
@@ -8,6 +8,7 @@ pub fn sort(input: &[String], options: Options) -> structs::Sorted {
     // Initialise variables
     let mut repo: Vec<String> = vec![];
     let mut aur: Vec<String> = vec![];
+    let mut custom: Vec<String> = vec![];
     let mut nf: Vec<String> = vec![];
     let verbosity = options.verbosity;
 
@@ -18,35 +19,55 @@ pub fn sort(input: &[String], options: Options) -> structs::Sorted {
         log!("Sorting: {:?}", a.join(" "));
     }
 
+    // Look in every place packages come from, in priority order
+    let priority = sources::priority();
     for b in a {
-        // Check if package is in the repos
-        let rs = Command::new("pacman")
-            .arg("-Ss")
-            .arg(format!("^{}$", &b))
-            .stdout(Stdio::null())
-            .status()
-            .expect("Something has gone wrong");
+        let found = priority.iter().find(|place| match place.as_str() {
+            // Check if package is in the repos
+            "repo" => {
+                Command::new("pacman")
+                    .arg("-Ss")
+                    .arg(format!("^{}$", &b))
+                    .stdout(Stdio::null())
+                    .status()
+                    .expect("Something has gone wrong")
+                    .code()
+                    == Some(0)
+            }
+            // Check if it is in the AUR
+            "aur" => rpc::rpcinfo(&b).found,
+            // Check if a custom source has a PKGBUILD for it
+            source => sources::find(source, &b).is_some(),
+        });
 
-        if rs.code() == Some(0) {
-            // If it is, add it to the repo vector
-            if verbosity >= 1 {
-                log!("{} found in repos", b);
+        match found.map(String::as_str) {
+            Some("repo") => {
+                if verbosity >= 1 {
+                    log!("{} found in repos", b);
+                }
+                repo.push(b.to_string());
+            }
+            Some("aur") => {
+                if verbosity >= 1 {
+                    log!("{} found in AUR", b);
+                }
+                aur.push(b.to_string());
             }
-            repo.push(b.to_string());
-        } else if rpc::rpcinfo(&b).found {
-            // Otherwise, check if it is in the AUR, if it is, add it to the AUR vector
-            if verbosity >= 1 {
-                log!("{} found in AUR", b);
+            Some(source) => {
+                if verbosity >= 1 {
+                    log!("{} found in source {}", b, source);
+                }
+                custom.push(b.clone());
             }
-            aur.push(b.to_string());
-        } else {
-            // Otherwise, add it to the not found vector
-            if verbosity >= 1 {
-                log!("{} not found", b);
+            None => {
+                // Otherwise, add it to the not found vector
+                if verbosity >= 1 {
+                    log!("{} not found", b);
+                }
+                nf.push(b.to_string());
             }
-            nf.push(b.to_string());
         }
     }
 
-    structs::Sorted::new(repo, aur, nf)
+    structs::Sorted::new(repo, aur, custom, nf)
 }
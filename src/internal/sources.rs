@@ -0,0 +1,146 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::{env, fs};
+
+use crate::internal::commands::ShellCommand;
+use crate::internal::config::{self, ConfigSource};
+use crate::internal::error::SilentUnwrap;
+use crate::internal::exit_code::AppExitCode;
+use crate::internal::local_source::{self, parse_srcinfo, LocalSource};
+use crate::{crash, info, warn};
+
+const SOURCE_CACHE: &str = ".cache/ame/sources";
+
+/// Git sources already pulled during this run
+static SYNCED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+/// Packages already looked up during this run, by source and package name
+static FOUND: Mutex<BTreeMap<(String, String), Option<LocalSource>>> = Mutex::new(BTreeMap::new());
+
+/// Returns where packages are looked for, in order: `repo`, `aur` and the names of custom sources.
+/// Without a configured order the repos come first, then custom sources, then the AUR.
+pub fn priority() -> Vec<String> {
+    let config = config::read();
+    config.base.source_priority.unwrap_or_else(|| {
+        let mut order = vec!["repo".to_string()];
+        order.extend(config.sources.iter().map(|s| s.name.clone()));
+        order.push("aur".to_string());
+        order
+    })
+}
+
+/// Returns the directory a custom source's PKGBUILDs are in, cloning or updating it first if it's a git repo
+fn source_dir(source: &ConfigSource) -> String {
+    let url = match (&source.url, &source.path) {
+        (Some(url), _) => url.strip_prefix("git+").unwrap_or(url),
+        (None, Some(path)) => return path.clone(),
+        (None, None) => crash!(
+            AppExitCode::ConfigParseError,
+            "Source {} needs either a url or a path",
+            source.name
+        ),
+    };
+    let dir = format!(
+        "{}/{}/{}",
+        env::var("HOME").unwrap(),
+        SOURCE_CACHE,
+        source.name
+    );
+
+    // Only pull once per run, sorting looks packages up many times
+    let mut synced = SYNCED.lock().unwrap();
+    if !synced.contains(&source.name) {
+        info!("Updating PKGBUILDs from {}", source.name);
+        let git = if Path::new(&dir).join(".git").exists() {
+            ShellCommand::git().args(["-C", &dir, "pull", "--ff-only"])
+        } else {
+            ShellCommand::git().args(["clone", url, &dir])
+        };
+        let status = git.wait().silent_unwrap(AppExitCode::GitError);
+        if !status.success() {
+            crash!(
+                AppExitCode::GitError,
+                "Failed to update source {}",
+                source.name
+            );
+        }
+        synced.push(source.name.clone());
+    }
+
+    dir
+}
+
+/// Finds the directory of the PKGBUILD building a package in a source tree.
+/// Both one directory per package base and ABS-style `<base>/trunk` layouts are understood.
+fn find_in(dir: &str, name: &str) -> Option<PathBuf> {
+    let mut entries = fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect::<Vec<PathBuf>>();
+    entries.sort();
+
+    for entry in entries {
+        for candidate in [entry.clone(), entry.join("trunk")] {
+            if !candidate.join("PKGBUILD").is_file() {
+                continue;
+            }
+            // Split packages are only known by their .SRCINFO
+            let matches = entry.file_name().is_some_and(|n| n == name)
+                || fs::read_to_string(candidate.join(".SRCINFO"))
+                    .is_ok_and(|s| parse_srcinfo(&s).names.iter().any(|n| n == name));
+            if matches {
+                return Some(candidate);
+            }
+        }
+    }
+
+    None
+}
+
+/// Looks a package up in one custom source by its name in config.
+/// Sorting and planning look packages up many times, so each is only loaded once per run.
+pub fn find(source: &str, name: &str) -> Option<LocalSource> {
+    let key = (source.to_string(), name.to_string());
+    if let Some(found) = FOUND.lock().unwrap().get(&key) {
+        return found.clone();
+    }
+
+    let found = load(source, name);
+    FOUND.lock().unwrap().insert(key, found.clone());
+    found
+}
+
+/// Loads the PKGBUILD building a package from one custom source
+fn load(source: &str, name: &str) -> Option<LocalSource> {
+    let config = config::read();
+    let source = config.sources.iter().find(|s| s.name == source)?;
+    let dir = find_in(&source_dir(source), name)?;
+
+    // Nothing may run a PKGBUILD before it's reviewed, so its .SRCINFO has to say what it builds
+    if !dir.join(".SRCINFO").is_file() {
+        warn!(
+            "Skipping {} from source {}, it has no .SRCINFO",
+            dir.display(),
+            source.name
+        );
+        return None;
+    }
+
+    Some(local_source::load_dir(&dir.display().to_string(), false))
+}
+
+/// Looks a package up in the custom sources, in priority order
+pub fn find_any(name: &str) -> Option<LocalSource> {
+    priority()
+        .iter()
+        .filter(|s| *s != "repo" && *s != "aur")
+        .find_map(|source| find(source, name))
+}
+
+/// Finds the PKGBUILD an installed package that isn't in the AUR is built from:
+/// a custom source, or the directory or git URL it was installed from
+pub fn find_installed(name: &str) -> Option<LocalSource> {
+    find_any(name).or_else(|| local_source::installed_from(name))
+}
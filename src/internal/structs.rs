@@ -5,13 +5,25 @@ pub struct Sorted {
     pub repo: Vec<String>,
     #[allow(dead_code)]
     pub aur: Vec<String>,
+    /// Packages with a PKGBUILD in one of the custom sources from config
+    pub custom: Vec<String>,
     #[allow(dead_code)]
     pub nf: Vec<String>,
 }
 
 impl Sorted {
-    pub fn new(repo: Vec<String>, aur: Vec<String>, nf: Vec<String>) -> Self {
-        Self { repo, aur, nf }
+    pub const fn new(
+        repo: Vec<String>,
+        aur: Vec<String>,
+        custom: Vec<String>,
+        nf: Vec<String>,
+    ) -> Self {
+        Self {
+            repo,
+            aur,
+            custom,
+            nf,
+        }
     }
 }
 
@@ -21,4 +33,12 @@ pub struct Options {
     pub verbosity: usize,
     pub noconfirm: bool,
     pub asdeps: bool,
+    pub build: BuildOptions,
+}
+
+#[derive(Clone, Copy, Default)]
+/// How AUR packages get built, set by the flags of the command building them
+pub struct BuildOptions {
+    pub keep_going: bool,
+    pub nocheck: bool,
 }
@@ -0,0 +1,281 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::os::unix::fs::symlink;
+use std::path::Path;
+use std::process::Command;
+use std::{env, fs};
+
+use crate::internal::commands::ShellCommand;
+use crate::internal::error::{AppError, AppResult};
+use crate::internal::foreign::QueriedPackage;
+use crate::internal::history::Source;
+use crate::internal::local_source::LocalSource;
+use crate::internal::rpc::{try_rpcinfo, InfoResults, Package};
+use crate::internal::sources;
+use crate::internal::utils::{run_parallel, vercmp, Spinner};
+use crate::Options;
+
+const LOCAL_DB: &str = "/var/lib/pacman/local";
+const UPDATE_CACHE: &str = ".cache/ame/updates.json";
+/// How many AUR queries run at the same time
+const RPC_WORKERS: usize = 16;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+/// A package with a newer version available
+pub struct Update {
+    pub name: String,
+    pub old_version: String,
+    pub new_version: String,
+    pub source: Source,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+/// The result of the last background update check
+pub struct UpdateCache {
+    pub timestamp: i64,
+    pub updates: Vec<Update>,
+}
+
+/// The result of comparing installed foreign packages against the AUR
+pub struct AurCheck {
+    pub upgrades: Vec<(QueriedPackage, Package)>,
+    /// Packages built from PKGBUILDs outside the AUR with a newer version there
+    pub source_upgrades: Vec<(QueriedPackage, LocalSource)>,
+    pub missing: Vec<QueriedPackage>,
+}
+
+/// Compares installed foreign packages against their AUR versions.
+/// The AUR is queried by a bounded pool of workers, with progress shown on `spinner` if given.
+/// Packages missing from the AUR are compared against the PKGBUILD they were built from instead, if known.
+/// Fails with the first error if the AUR couldn't be queried for any of them.
+pub fn check_aur(
+    foreign: Vec<QueriedPackage>,
+    options: Options,
+    mut spinner: Option<&mut Spinner>,
+) -> AppResult<AurCheck> {
+    let verbosity = options.verbosity;
+    let total = foreign.len();
+    let mut upgrades = vec![];
+    let mut missing = vec![];
+    let mut error = None;
+
+    let mut done = 0;
+    run_parallel(
+        foreign,
+        RPC_WORKERS,
+        |pkg| {
+            let rpc_result = try_rpcinfo(&pkg.name);
+            (pkg, rpc_result)
+        },
+        |(pkg, rpc_result)| {
+            done += 1;
+            if let Some(sp) = spinner.as_mut() {
+                sp.update(&format!("Checking AUR upgrades... ({done}/{total})"));
+            }
+            match rpc_result {
+                Ok(rpc_result) => {
+                    compare(pkg, rpc_result, &mut upgrades, &mut missing, verbosity);
+                }
+                Err(e) => {
+                    error.get_or_insert(e);
+                }
+            }
+        },
+    );
+    if let Some(e) = error {
+        return Err(e);
+    }
+
+    // Workers finish in any order, keep the output stable
+    upgrades.sort_by(|(a, _), (b, _)| a.name.cmp(&b.name));
+    missing.sort_by(|a, b| a.name.cmp(&b.name));
+
+    // Packages from custom sources or local PKGBUILDs aren't in the AUR, but aren't orphaned either
+    let mut source_upgrades = vec![];
+    missing.retain(|pkg| match sources::find_installed(&pkg.name) {
+        Some(source) => {
+            if verbosity >= 1 {
+                log!(
+                    "{} {} is built from {} at version {}",
+                    pkg.name,
+                    pkg.version,
+                    source.dir,
+                    source.info.version
+                );
+            }
+            if vercmp(&pkg.version, &source.info.version) == Ordering::Less {
+                source_upgrades.push((pkg.clone(), source));
+            }
+            false
+        }
+        None => true,
+    });
+
+    Ok(AurCheck {
+        upgrades,
+        source_upgrades,
+        missing,
+    })
+}
+
+/// Sorts a queried package into upgrades or missing packages depending on its AUR info
+fn compare(
+    pkg: QueriedPackage,
+    rpc_result: InfoResults,
+    upgrades: &mut Vec<(QueriedPackage, Package)>,
+    missing: &mut Vec<QueriedPackage>,
+    verbosity: usize,
+) {
+    if !rpc_result.found {
+        // If package not found, remember it and skip
+        missing.push(pkg);
+        return;
+    }
+    let aur_package = rpc_result.package.unwrap();
+
+    // Compare versions, keeping packages with a newer version in the AUR
+    let ordering = vercmp(&pkg.version, &aur_package.version);
+    if verbosity >= 1 {
+        log!(
+            "{} {} is {:?} AUR version {}",
+            pkg.name,
+            pkg.version,
+            ordering,
+            aur_package.version
+        );
+    }
+    if ordering == Ordering::Less {
+        upgrades.push((pkg, aur_package));
+    }
+}
+
+/// Turns the upgrades found by [`check_aur`] into [`Update`]s
+pub fn aur_updates(check: &AurCheck) -> Vec<Update> {
+    let aur = check.upgrades.iter().map(|(pkg, aur_package)| Update {
+        name: pkg.name.clone(),
+        old_version: pkg.version.clone(),
+        new_version: aur_package.version.clone(),
+        source: Source::Aur,
+    });
+    let sources = check.source_upgrades.iter().map(|(pkg, source)| Update {
+        name: pkg.name.clone(),
+        old_version: pkg.version.clone(),
+        new_version: source.info.version.clone(),
+        source: Source::Aur,
+    });
+
+    aur.chain(sources).collect()
+}
+
+/// Lists repo updates without touching the system's sync databases, the way `checkupdates` does:
+/// the databases are synced into a temporary, user-owned copy with `fakeroot`.
+pub fn repo_updates(options: Options) -> AppResult<Vec<Update>> {
+    let verbosity = options.verbosity;
+    let tmpdir = env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
+    let dbpath = format!("{}/ame-checkup-db-{}", tmpdir, unsafe { libc::getuid() });
+
+    // Link the real local database into the temporary database path
+    fs::create_dir_all(&dbpath)?;
+    let local = format!("{dbpath}/local");
+    if !Path::new(&local).exists() {
+        symlink(LOCAL_DB, &local)?;
+    }
+
+    if verbosity >= 1 {
+        log!("Syncing databases into {}", dbpath);
+    }
+    let sync = ShellCommand::fakeroot()
+        .args(["--", "pacman", "-Sy", "--dbpath", &dbpath])
+        .args(["--logfile", "/dev/null"])
+        .wait_with_output()?;
+    if !sync.status.success() {
+        return Err(AppError::from(format!(
+            "Syncing temporary databases failed: {}",
+            sync.stderr.trim()
+        )));
+    }
+
+    // `pacman -Qu` exits with 1 when there is nothing to upgrade, so only look at the output
+    let output = Command::new("pacman")
+        .args(["-Qu", "--dbpath", &dbpath, "--color", "never"])
+        .output()?;
+    let updates = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            // Lines look like `name old -> new`, followed by `[ignored]` for packages pacman won't upgrade
+            let parts = line.split_whitespace().collect::<Vec<&str>>();
+            if parts.len() == 4 && parts[2] == "->" {
+                Some(Update {
+                    name: parts[0].to_string(),
+                    old_version: parts[1].to_string(),
+                    new_version: parts[3].to_string(),
+                    source: Source::Repo,
+                })
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    Ok(updates)
+}
+
+fn cache_path() -> String {
+    format!("{}/{}", env::var("HOME").unwrap(), UPDATE_CACHE)
+}
+
+/// Reads the result of the last background update check, if there is one
+pub fn read_cache() -> Option<UpdateCache> {
+    fs::read_to_string(cache_path())
+        .ok()
+        .and_then(|cache| serde_json::from_str(&cache).ok())
+}
+
+/// Stores the result of a background update check
+pub fn write_cache(cache: &UpdateCache) -> AppResult<()> {
+    let cache = serde_json::to_string(cache).map_err(|e| AppError::from(e.to_string()))?;
+    fs::write(cache_path(), cache)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(installed: &str, aur: Option<&str>) -> (usize, usize) {
+        let pkg = QueriedPackage {
+            name: "foo".to_string(),
+            version: installed.to_string(),
+        };
+        let rpc_result = InfoResults {
+            found: aur.is_some(),
+            package: aur.map(|version| {
+                serde_json::from_str(&format!(r#"{{"Name": "foo", "Version": "{version}"}}"#))
+                    .unwrap()
+            }),
+        };
+
+        let (mut upgrades, mut missing) = (vec![], vec![]);
+        compare(pkg, rpc_result, &mut upgrades, &mut missing, 0);
+        (upgrades.len(), missing.len())
+    }
+
+    #[test]
+    fn newer_in_aur() {
+        // Also covers packages installed from the local repo, once `list_foreign` lists them
+        assert_eq!(check("1.0-1", Some("1.0-2")), (1, 0));
+        assert_eq!(check("1.0-1", Some("1:0.9-1")), (1, 0));
+    }
+
+    #[test]
+    fn up_to_date() {
+        assert_eq!(check("1.0-1", Some("1.0-1")), (0, 0));
+        assert_eq!(check("1.1-1", Some("1.0-1")), (0, 0));
+    }
+
+    #[test]
+    fn missing_from_aur() {
+        assert_eq!(check("1.0-1", None), (0, 1));
+    }
+}
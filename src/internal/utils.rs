@@ -1,7 +1,10 @@
 use colored::Colorize;
+use std::cmp::Ordering;
 use std::io;
 use std::io::Write;
 use std::process::{exit, Command, Stdio};
+use std::sync::{mpsc, Mutex};
+use std::thread;
 use std::time::UNIX_EPOCH;
 use textwrap::wrap;
 
@@ -53,7 +56,10 @@ macro_rules! log {
 macro_rules! prompt {
     (default $default:expr, $($arg:tt)+) => {
         $crate::internal::utils::prompt_yn(format!($($arg)+), $default)
-    }
+    };
+    (input, $($arg:tt)+) => {
+        $crate::internal::utils::prompt_input(format!($($arg)+))
+    };
 }
 
 #[macro_export]
@@ -116,6 +122,11 @@ pub fn log_and_crash(msg: String, exit_code: AppExitCode) -> ! {
         ERR_SYMBOL.red().bold(),
         wrap(&msg, opts).join("\n").red().bold()
     );
+
+    // Make sure an interrupted transaction still runs its post hooks and ends up in the history log
+    internal::hooks::fail();
+    internal::history::fail();
+
     exit(exit_code as i32);
 }
 
@@ -175,6 +186,31 @@ pub fn prompt_yn(question: String, default_true: bool) -> bool {
     }
 }
 
+/// Prompts the user for a line of free-form input.
+pub fn prompt_input(question: String) -> String {
+    let question = if internal::uwu_enabled() {
+        uwu!(&question)
+    } else {
+        question
+    };
+
+    let opts = textwrap::Options::new(crossterm::terminal::size().unwrap().0 as usize - 2)
+        .subsequent_indent("  ");
+
+    print!(
+        "{} {}: ",
+        PROMPT_SYMBOL.purple(),
+        wrap(&question, opts).join("\n").bold(),
+    );
+
+    let mut input: String = String::new();
+
+    io::stdout().flush().ok();
+    io::stdin().read_line(&mut input).unwrap();
+
+    input.trim().to_string()
+}
+
 pub struct Spinner {
     spinner: spinoff::Spinner,
 }
@@ -195,6 +231,17 @@ impl Spinner {
 
         self.spinner.stop_and_persist(&symbol, &text);
     }
+
+    /// Replaces the spinner's text, e.g. to show progress
+    pub fn update(&mut self, text: &str) {
+        let text = if internal::uwu_enabled() {
+            uwu!(text)
+        } else {
+            text.to_string()
+        };
+
+        self.spinner.update_text(format!("{}", text.bold()));
+    }
 }
 
 /// Returns a spinner that can be used to display progress.
@@ -237,3 +284,266 @@ pub fn pager(text: &String) -> io::Result<()> {
 
     Ok(())
 }
+
+/// Runs `job` on every item using at most `workers` threads, handing results to `on_done` as they finish.
+pub fn run_parallel<T, R, F, D>(items: Vec<T>, workers: usize, job: F, mut on_done: D)
+where
+    T: Send,
+    R: Send,
+    F: Fn(T) -> R + Sync,
+    D: FnMut(R),
+{
+    let workers = workers.min(items.len());
+    let queue = Mutex::new(items.into_iter());
+    let (tx, rx) = mpsc::channel();
+
+    thread::scope(|s| {
+        // Each worker takes the next item off the queue until it's empty
+        for _ in 0..workers {
+            let tx = tx.clone();
+            let (queue, job) = (&queue, &job);
+            s.spawn(move || loop {
+                let item = queue.lock().unwrap().next();
+                match item {
+                    Some(item) => {
+                        if tx.send(job(item)).is_err() {
+                            break;
+                        }
+                    }
+                    None => break,
+                }
+            });
+        }
+        drop(tx);
+
+        for result in rx {
+            on_done(result);
+        }
+    });
+}
+
+/// Compares two version segments the way pacman's `rpmvercmp` does.
+fn rpmvercmp(a: &str, b: &str) -> Ordering {
+    if a == b {
+        return Ordering::Equal;
+    }
+
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    let (mut one, mut two) = (0, 0);
+    let (mut prev_one, mut prev_two) = (0, 0);
+
+    while one < a.len() && two < b.len() {
+        // Skip separators
+        while one < a.len() && !a[one].is_ascii_alphanumeric() {
+            one += 1;
+        }
+        while two < b.len() && !b[two].is_ascii_alphanumeric() {
+            two += 1;
+        }
+        if one >= a.len() || two >= b.len() {
+            break;
+        }
+
+        // A longer run of separators makes the version newer
+        if one - prev_one != two - prev_two {
+            return (one - prev_one).cmp(&(two - prev_two));
+        }
+
+        // Grab the next completely numeric or completely alphabetic segment
+        let is_num = a[one].is_ascii_digit();
+        let matches = |c: &u8| {
+            if is_num {
+                c.is_ascii_digit()
+            } else {
+                c.is_ascii_alphabetic()
+            }
+        };
+        let end_one = one + a[one..].iter().take_while(|c| matches(c)).count();
+        let end_two = two + b[two..].iter().take_while(|c| matches(c)).count();
+
+        // Numeric segments are always newer than alphabetic ones
+        if end_two == two {
+            return if is_num {
+                Ordering::Greater
+            } else {
+                Ordering::Less
+            };
+        }
+
+        let (mut seg_one, mut seg_two) = (&a[one..end_one], &b[two..end_two]);
+        if is_num {
+            // Compare numbers by length after dropping leading zeros, then lexically
+            while seg_one.first() == Some(&b'0') {
+                seg_one = &seg_one[1..];
+            }
+            while seg_two.first() == Some(&b'0') {
+                seg_two = &seg_two[1..];
+            }
+            if seg_one.len() != seg_two.len() {
+                return seg_one.len().cmp(&seg_two.len());
+            }
+        }
+        if seg_one != seg_two {
+            return seg_one.cmp(seg_two);
+        }
+
+        one = end_one;
+        two = end_two;
+        prev_one = one;
+        prev_two = two;
+    }
+
+    if one >= a.len() && two >= b.len() {
+        return Ordering::Equal;
+    }
+
+    // Whichever version has something left is newer, unless that's an alphabetic suffix like `1.0a` vs `1.0`
+    if (one >= a.len() && !b.get(two).is_some_and(u8::is_ascii_alphabetic))
+        || a.get(one).is_some_and(u8::is_ascii_alphabetic)
+    {
+        Ordering::Less
+    } else {
+        Ordering::Greater
+    }
+}
+
+/// Splits a version into its epoch, version and optional release.
+fn parse_evr(evr: &str) -> (&str, &str, Option<&str>) {
+    let digits = evr.bytes().take_while(u8::is_ascii_digit).count();
+    let (epoch, rest) = if evr[digits..].starts_with(':') {
+        let epoch = &evr[..digits];
+        (
+            if epoch.is_empty() { "0" } else { epoch },
+            &evr[digits + 1..],
+        )
+    } else {
+        ("0", evr)
+    };
+
+    match rest.rsplit_once('-') {
+        Some((version, release)) => (epoch, version, Some(release)),
+        None => (epoch, rest, None),
+    }
+}
+
+/// Compares two package versions like pacman's `vercmp`, without spawning it.
+pub fn vercmp(a: &str, b: &str) -> Ordering {
+    if a == b {
+        return Ordering::Equal;
+    }
+
+    let (epoch_a, version_a, release_a) = parse_evr(a);
+    let (epoch_b, version_b, release_b) = parse_evr(b);
+
+    rpmvercmp(epoch_a, epoch_b)
+        .then_with(|| rpmvercmp(version_a, version_b))
+        .then_with(|| match (release_a, release_b) {
+            (Some(release_a), Some(release_b)) => rpmvercmp(release_a, release_b),
+            _ => Ordering::Equal,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::vercmp;
+    use std::cmp::Ordering::{self, Equal, Greater, Less};
+
+    /// Checks a comparison both ways round, like pacman's `vercmptest`
+    fn check(a: &str, b: &str, expected: Ordering) {
+        assert_eq!(vercmp(a, b), expected, "vercmp {a} {b}");
+        assert_eq!(vercmp(b, a), expected.reverse(), "vercmp {b} {a}");
+    }
+
+    #[test]
+    fn plain_versions() {
+        check("1.5.0", "1.5.0", Equal);
+        check("1.5.1", "1.5.0", Greater);
+        check("1.5.1", "1.5", Greater);
+    }
+
+    #[test]
+    fn pkgrel() {
+        check("1.5.0-1", "1.5.0-1", Equal);
+        check("1.5.0-1", "1.5.0-2", Less);
+        check("1.5.0-1", "1.5.1-1", Less);
+        check("1.5.0-2", "1.5.1-1", Less);
+        check("1.5-1", "1.5.1-1", Less);
+        check("1.5-2", "1.5.1-1", Less);
+        check("1.5-2", "1.5.1-2", Less);
+    }
+
+    #[test]
+    fn pkgrel_on_one_side() {
+        check("1.5", "1.5-1", Equal);
+        check("1.5-1", "1.5", Equal);
+        check("1.1-1", "1.1", Equal);
+        check("1.0-1", "1.1", Less);
+        check("1.1-1", "1.0", Greater);
+    }
+
+    #[test]
+    fn alphanumeric() {
+        check("1.5b-1", "1.5-1", Less);
+        check("1.5b", "1.5", Less);
+        check("1.5b-1", "1.5", Less);
+        check("1.5b", "1.5.1", Less);
+        check("1.0a", "1.0alpha", Less);
+        check("1.0alpha", "1.0b", Less);
+        check("1.0b", "1.0beta", Less);
+        check("1.0beta", "1.0rc", Less);
+        check("1.0rc", "1.0", Less);
+    }
+
+    #[test]
+    fn alpha_dotted() {
+        check("1.5.a", "1.5", Greater);
+        check("1.5.b", "1.5.a", Greater);
+        check("1.5.1", "1.5.b", Greater);
+        check("1.5.b-1", "1.5.b", Equal);
+        check("1.5-1", "1.5.b", Less);
+    }
+
+    #[test]
+    fn separators() {
+        check("2.0", "2_0", Equal);
+        check("2.0_a", "2_0.a", Equal);
+        check("2.0a", "2.0.a", Less);
+        check("2___a", "2_a", Greater);
+        check("1..0", "1.0", Greater);
+        check("1.0.", "1.0", Greater);
+    }
+
+    #[test]
+    fn tildes() {
+        // Unlike rpm, pacman has no special meaning for `~`, it's just a separator
+        check("1.0~rc1", "1.0", Greater);
+        check("1.0~rc1", "1.0.rc1", Equal);
+        check("1.0~rc1", "1.0~rc2", Less);
+    }
+
+    #[test]
+    fn epochs() {
+        check("0:1.0", "0:1.0", Equal);
+        check("0:1.0", "0:1.1", Less);
+        check("1:1.0", "0:1.0", Greater);
+        check("1:1.0", "0:1.1", Greater);
+        check("1:1.0", "2:1.1", Less);
+        check("1:1.0", "0:1.0-1", Greater);
+        check("1:1.0-1", "0:1.1-1", Greater);
+        check("0:1.0", "1.0", Equal);
+        check("0:1.0", "1.1", Less);
+        check("0:1.1", "1.0", Greater);
+        check("1:1.0", "1.0", Greater);
+        check("1:1.0", "1.1", Greater);
+        check("1:1.1", "1.1", Greater);
+        check(":1.0", "0:1.0", Equal);
+    }
+
+    #[test]
+    fn numbers() {
+        check("1.010", "1.10", Equal);
+        check("1.9", "1.10", Less);
+        check("20220101", "1.0", Greater);
+        check("r1234.abcdef", "r999.fedcba", Greater);
+    }
+}
@@ -4,6 +4,7 @@
 use args::Args;
 use clap::{CommandFactory, Parser};
 use clap_complete::{Generator, Shell};
+use colored::Colorize;
 use internal::commands::ShellCommand;
 use internal::error::SilentUnwrap;
 use std::env;
@@ -12,11 +13,20 @@ use std::path::Path;
 use std::str::FromStr;
 
 use crate::args::{
-    GenCompArgs, InfoArgs, InstallArgs, Operation, QueryArgs, RemoveArgs, SearchArgs, UpgradeArgs,
+    BuildArgs, DaemonAction, DaemonArgs, DowngradeArgs, GenCompArgs, HistoryArgs, InfoArgs,
+    InstallArgs, Operation, QueryArgs, RemoveArgs, RollbackArgs, SearchArgs, UpgradeArgs,
 };
 use crate::internal::exit_code::AppExitCode;
+use crate::internal::foreign::{find_orphaned, list_foreign};
+use crate::internal::history::{self, TransactionKind};
+use crate::internal::local_source::{self, is_local_source, LocalSource};
+use crate::internal::plan::InstallState;
+use crate::internal::rpc::{rpcinfo, SearchBy};
+use crate::internal::sources;
 use crate::internal::utils::pager;
-use crate::internal::{detect, init, sort, start_sudoloop, structs::Options};
+use crate::internal::{
+    detect, init, sort, start_sudoloop, structs::BuildOptions, structs::Options,
+};
 
 #[global_allocator]
 static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
@@ -43,6 +53,7 @@ fn main() {
         verbosity,
         noconfirm,
         asdeps: false,
+        build: BuildOptions::default(),
     };
 
     // Ensure amethyst is initialized
@@ -76,7 +87,25 @@ fn main() {
 
     // List of possible options
     let opers = vec![
-        "install", "remove", "upgrade", "search", "query", "info", "clean", "diff", "gencomp",
+        "install",
+        "remove",
+        "upgrade",
+        "search",
+        "query",
+        "info",
+        "clean",
+        "diff",
+        "gencomp",
+        "history",
+        "downgrade",
+        "rollback",
+        "checkupdates",
+        "daemon",
+        "news",
+        "rebuild-check",
+        "log",
+        "getpkgbuild",
+        "build",
     ];
 
     // If arg is completely unrecognized, attempt to pass it to pacman
@@ -104,17 +133,38 @@ fn main() {
         Operation::Install(install_args) => cmd_install(install_args, options, &cachedir),
         Operation::Remove(remove_args) => cmd_remove(remove_args, options),
         Operation::Search(search_args) => cmd_search(&search_args, options),
-        Operation::Query(query_args) => cmd_query(&query_args),
+        Operation::Query(query_args) => cmd_query(&query_args, options),
         Operation::Info(info_args) => cmd_info(info_args),
         Operation::Upgrade(upgrade_args) => cmd_upgrade(upgrade_args, options, &cachedir),
         Operation::Clean => {
             info!("Removing orphaned packages");
+            history::begin(TransactionKind::Clean, &[]);
             operations::clean(options);
+            history::finish();
         }
         Operation::Diff => {
             info!("Running pacdiff");
             detect();
         }
+        Operation::History(history_args) => cmd_history(&history_args),
+        Operation::Downgrade(downgrade_args) => cmd_downgrade(&downgrade_args, options),
+        Operation::Rollback(rollback_args) => cmd_rollback(&rollback_args, options),
+        Operation::Daemon(daemon_args) => cmd_daemon(daemon_args, options),
+        Operation::News(news_args) => operations::news(&news_args, options),
+        Operation::GetPkgbuild(getpkgbuild_args) => operations::getpkgbuild(
+            &getpkgbuild_args.packages,
+            getpkgbuild_args.output.as_deref(),
+            options,
+        ),
+        Operation::Build(build_args) => cmd_build(&build_args, options, &cachedir),
+        Operation::Log(log_args) => operations::show_log(&log_args.package, options),
+        Operation::RebuildCheck => {
+            history::begin(TransactionKind::Install, &[]);
+            finish_transaction(operations::rebuild_check(options, &cachedir));
+        }
+        Operation::CheckUpdates(checkupdates_args) => {
+            operations::checkupdates(&checkupdates_args, options);
+        }
         Operation::GenComp(gencomp_args) => {
             info!("Generating shell completions for {}. Please pipe `stderr` to a file to get completions as a file, e.g. `ame gencomp fish 2> file.fish`", gencomp_args.shell);
             cmd_gencomp(&gencomp_args);
@@ -122,6 +172,61 @@ fn main() {
     }
 }
 
+fn cmd_build(args: &BuildArgs, options: Options, cachedir: &str) {
+    let options = Options {
+        build: BuildOptions {
+            keep_going: args.keep_going,
+            nocheck: args.nocheck,
+        },
+        ..options
+    };
+
+    let sorted = sort(&args.packages, options);
+    if !sorted.nf.is_empty() {
+        crash!(
+            AppExitCode::PacmanError,
+            "Couldn't find packages: {} in repos or the AUR",
+            sorted.nf.join(", ")
+        );
+    }
+    // Repo packages are already built, there's nothing to do for them
+    for pkg in &sorted.repo {
+        warn!("{} is in the repos, only AUR packages are built", pkg);
+    }
+    if sorted.aur.is_empty() && sorted.custom.is_empty() {
+        return;
+    }
+    let local = sorted
+        .custom
+        .iter()
+        .filter_map(|pkg| sources::find_any(pkg))
+        .collect::<Vec<LocalSource>>();
+
+    // AUR dependencies get installed along the way, so keep a record of it
+    let mut targets = sorted.aur.clone();
+    targets.extend(sorted.custom.iter().cloned());
+    history::begin(TransactionKind::Install, &targets);
+    finish_transaction(operations::aur_build(
+        &sorted.aur,
+        &local,
+        options,
+        cachedir,
+        args.output.as_deref().unwrap_or("."),
+    ));
+}
+
+/// Ends the current transaction. If any packages failed to build it's recorded as failed along with them,
+/// and amethyst exits with an error so scripts can tell.
+fn finish_transaction(result: Result<(), Vec<String>>) {
+    match result {
+        Ok(()) => history::finish(),
+        Err(failed) => {
+            history::fail_with(&failed);
+            std::process::exit(AppExitCode::MakePkgError as i32);
+        }
+    }
+}
+
 fn cmd_install(args: InstallArgs, options: Options, cachedir: &str) {
     // Initialise variables
     let packages = args.packages;
@@ -130,13 +235,52 @@ fn cmd_install(args: InstallArgs, options: Options, cachedir: &str) {
         crash!(AppExitCode::Other, "Cannot specify both --aur and --repo");
     }
 
+    let options = Options {
+        build: BuildOptions {
+            keep_going: args.keep_going,
+            nocheck: args.nocheck,
+        },
+        ..options
+    };
+
+    if args.resume {
+        let state = InstallState::load_last().unwrap_or_else(|| {
+            crash!(
+                AppExitCode::Other,
+                "There is no unfinished AUR install to resume"
+            );
+        });
+        history::begin(TransactionKind::Install, &state.targets);
+        finish_transaction(operations::resume_aur_install(state, options));
+        return;
+    }
+
     let aur = args.aur || env::args().collect::<Vec<String>>()[1] == "-Sa";
     let repo = args.repo || env::args().collect::<Vec<String>>()[1] == "-Sr";
 
+    // PKGBUILD directories and git URLs get built like AUR packages, everything else is looked up by name
+    let (local, packages): (Vec<String>, Vec<String>) =
+        packages.into_iter().partition(|p| is_local_source(p));
+    let mut local = local
+        .iter()
+        .map(|target| {
+            let source = local_source::load(target);
+            // It's not in the AUR, so upgrades need to know where to find it again
+            local_source::remember(target, &source);
+            source
+        })
+        .collect::<Vec<LocalSource>>();
+    let mut targets = packages.clone();
+    for source in &local {
+        targets.extend(source.info.names.iter().cloned());
+    }
+
+    history::begin(TransactionKind::Install, &targets);
+
     let sorted = sort(&packages, options);
     let config = internal::config::read();
 
-    info!("Attempting to install packages: {}", packages.join(", "));
+    info!("Attempting to install packages: {}", targets.join(", "));
 
     if !sorted.nf.is_empty() {
         // If some packages are not found, crash
@@ -151,10 +295,19 @@ fn cmd_install(args: InstallArgs, options: Options, cachedir: &str) {
         // If repo packages found, install them
         operations::install(&sorted.repo, options);
     }
-    if !repo && !aur && !sorted.aur.is_empty() || aur && !sorted.aur.is_empty() {
-        // If AUR packages found, install them
-        operations::aur_install(sorted.aur, options, cachedir);
+    // Packages from custom sources are built from their PKGBUILDs just like local ones
+    for pkg in &sorted.custom {
+        local.extend(sources::find_any(pkg));
     }
+    let aur_targets = if !repo || aur { sorted.aur } else { vec![] };
+    let result = if !aur_targets.is_empty() || !local.is_empty() {
+        // If AUR packages or local PKGBUILDs found, install them
+        operations::aur_install_local(&aur_targets, &local, options, cachedir)
+    } else {
+        Ok(())
+    };
+
+    finish_transaction(result);
 
     // Show optional dependencies for installed packages
     if packages.len() > 1 && config.base.highlight_optdepends {
@@ -181,7 +334,9 @@ fn cmd_remove(args: RemoveArgs, options: Options) {
     info!("Uninstalling packages: {}", &packages.join(", "));
 
     // Remove packages
+    history::begin(TransactionKind::Remove, &packages);
     operations::uninstall(&packages, options);
+    history::finish();
 }
 
 fn cmd_search(args: &SearchArgs, options: Options) {
@@ -191,6 +346,12 @@ fn cmd_search(args: &SearchArgs, options: Options) {
     // Logic for searching
     let repo = args.repo || env::args().collect::<Vec<String>>()[1] == "-Ssr";
     let aur = args.aur || env::args().collect::<Vec<String>>()[1] == "-Ssa";
+
+    // Only the AUR can be searched by other fields
+    if repo && args.by.is_some() {
+        crash!(AppExitCode::Other, "Cannot search the repos with --by");
+    }
+    let aur = aur || args.by.is_some();
     let both = !repo && !aur;
 
     // Start repo spinner
@@ -208,13 +369,21 @@ fn cmd_search(args: &SearchArgs, options: Options) {
 
     // Start AUR spinner
     let aur_results = if aur || both {
-        // Strip query of any non-alphanumeric characters
-        let query_string = query_string.replace(|c: char| !c.is_alphanumeric() && c != '-', "");
+        // Strip query of any non-alphanumeric characters, other fields can also hold names like `lib32-foo_bar.so`
+        let by = args.by.unwrap_or_default();
+        let query_string = query_string.replace(
+            |c: char| {
+                !c.is_alphanumeric()
+                    && c != '-'
+                    && (by == SearchBy::NameDesc || !"_.+@".contains(c))
+            },
+            "",
+        );
 
-        let asp = spinner!("Searching AUR for {}", query_string);
+        let asp = spinner!("Searching AUR for {} by {}", query_string, by);
 
         // Search AUR
-        let ret = operations::aur_search(&query_string, options);
+        let ret = operations::aur_search(&query_string, by, options);
         asp.stop_bold("AUR search complete");
 
         ret
@@ -241,7 +410,29 @@ fn cmd_search(args: &SearchArgs, options: Options) {
     }
 }
 
-fn cmd_query(args: &QueryArgs) {
+fn cmd_query(args: &QueryArgs, options: Options) {
+    if args.orphaned_foreign {
+        // Check every foreign package against the AUR and repos
+        let sp = spinner!("Checking foreign packages against the AUR");
+        let missing = list_foreign(options)
+            .into_iter()
+            .filter(|pkg| !rpcinfo(&pkg.name).found)
+            .collect();
+        let orphaned = find_orphaned(missing, options);
+        sp.stop_bold("Finished!");
+
+        if orphaned.is_empty() {
+            info!("No orphaned foreign packages found");
+        }
+        for pkg in orphaned {
+            println!("{} {}", pkg.name.bold(), pkg.version.green().bold());
+            if !pkg.successors.is_empty() {
+                println!("    Possible successors: {}", pkg.successors.join(", "));
+            }
+        }
+        return;
+    }
+
     let aur = args.aur
         || env::args().collect::<Vec<String>>()[1] == "-Qa"
         || env::args().collect::<Vec<String>>()[1] == "-Qm";
@@ -287,7 +478,55 @@ fn cmd_info(args: InfoArgs) {
 
 fn cmd_upgrade(args: UpgradeArgs, options: Options, cachedir: &str) {
     info!("Performing system upgrade");
-    operations::upgrade(options, args, cachedir);
+    let options = Options {
+        build: BuildOptions {
+            keep_going: args.keep_going,
+            nocheck: args.nocheck,
+        },
+        ..options
+    };
+    history::begin(TransactionKind::Upgrade, &[]);
+    finish_transaction(operations::upgrade(options, args, cachedir));
+}
+
+fn cmd_history(args: &HistoryArgs) {
+    let kind = args.kind.as_ref().map(|kind| {
+        TransactionKind::from_str(kind).unwrap_or_else(|e| {
+            crash!(AppExitCode::Other, "Invalid transaction kind: {}", e);
+        })
+    });
+
+    if let Some(id) = args.id {
+        operations::show_transaction(id);
+    } else {
+        operations::list_history(args.package.as_deref(), kind, args.limit);
+    }
+}
+
+fn cmd_downgrade(args: &DowngradeArgs, options: Options) {
+    info!("Downgrading {}", args.package);
+    history::begin(
+        TransactionKind::Downgrade,
+        std::slice::from_ref(&args.package),
+    );
+    operations::downgrade(&args.package, options);
+    history::finish();
+}
+
+fn cmd_rollback(args: &RollbackArgs, options: Options) {
+    history::begin(TransactionKind::Rollback, &[]);
+    operations::rollback(args.id, options);
+    history::finish();
+}
+
+fn cmd_daemon(args: DaemonArgs, options: Options) {
+    match args.action {
+        DaemonAction::InstallTimer(timer_args) => {
+            operations::install_timer(&timer_args.interval, options);
+        }
+        DaemonAction::RemoveTimer => operations::remove_timer(options),
+        DaemonAction::Check => operations::background_check(options),
+    }
 }
 
 fn cmd_gencomp(args: &GenCompArgs) {
@@ -1,39 +1,36 @@
 use chrono::{Local, TimeZone};
+use colored::Colorize;
+use std::collections::BTreeMap;
+use std::env;
 use std::env::set_current_dir;
+use std::fmt::Write;
+use std::fs;
 use std::path::Path;
 use std::process::Command;
-use std::{env, fs};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
 
+use crate::internal::archive::{archive_built, parse_filename};
 use crate::internal::commands::ShellCommand;
-use crate::internal::config;
+use crate::internal::config::{self, ConfigPackage, RemoveMakeDeps};
 use crate::internal::error::SilentUnwrap;
 use crate::internal::exit_code::AppExitCode;
-use crate::internal::rpc::rpcinfo;
-use crate::internal::sort;
-use crate::operations::install;
-use crate::{crash, info, log, prompt, warn, Options};
+use crate::internal::history::TransactionKind;
+use crate::internal::hooks;
+use crate::internal::local_repo;
+use crate::internal::local_source::LocalSource;
+use crate::internal::logs::{log_path, tail};
+use crate::internal::plan::{InstallState, Plan, PlannedBuild, Step};
+use crate::internal::utils::{run_parallel, Spinner};
+use crate::operations::install_logged;
+use crate::{crash, info, log, prompt, spinner, warn, Options};
 
 const AUR_CACHE: &str = ".cache/ame";
-
-/// Return a list of all files/dirs in a directory.
-fn list(dir: &str) -> Vec<String> {
-    let dirs = fs::read_dir(Path::new(&dir)).unwrap();
-    let dirs: Vec<String> = dirs
-        .map(|dir| {
-            (*dir
-                .unwrap()
-                .path()
-                .to_str()
-                .unwrap()
-                .split('/')
-                .collect::<Vec<&str>>()
-                .last()
-                .unwrap())
-            .to_string()
-        })
-        .collect();
-    dirs
-}
+/// How many clones and source downloads run at the same time
+const DOWNLOAD_WORKERS: usize = 8;
+/// The system makepkg.conf, used when a package doesn't set its own
+const MAKEPKG_CONF: &str = "/etc/makepkg.conf";
 
 /// Returns and creates a temporary directory for amethyst to use
 fn mktemp() -> String {
@@ -47,7 +44,7 @@ fn mktemp() -> String {
 }
 
 /// Help the user review and/or edit an AUR package before installing
-fn review(cachedir: &str, pkg: &str, orig_cachedir: &str) {
+fn review(cachedir: &str, pkg: &str) {
     // Prompt user to view PKGBUILD
     let p0 = prompt!(default false, "Would you like to review and/or edit {}'s PKGBUILD (and any adjacent build files if present)?", pkg);
     if p0 {
@@ -105,324 +102,1047 @@ fn review(cachedir: &str, pkg: &str, orig_cachedir: &str) {
             };
         }
     }
+}
+
+/// The stages every AUR package passes through, in order
+#[derive(Clone, Copy)]
+enum Stage {
+    Clone,
+    Review,
+    Fetch,
+    Build,
+}
 
-    // Prompt user to continue
-    let p = prompt!(default true, "Would you still like to install {}?", pkg);
-    if !p {
-        // If not, crash
-        if orig_cachedir.is_empty() {
-            fs::remove_dir_all(format!("{}/{}", cachedir, pkg)).unwrap();
+impl Stage {
+    const fn describe(self) -> &'static str {
+        match self {
+            Self::Clone => "Cloning",
+            Self::Review => "Reviewing",
+            Self::Fetch => "Fetching sources for",
+            Self::Build => "Building",
         }
-        crash!(AppExitCode::UserCancellation, "Not proceeding");
-    };
+    }
 }
 
-/// Finalize a build/install process
-fn finish(cachedir: &str, pkg: &str, options: &Options) {
-    // Install all packages from cachedir except `pkg` using --asdeps
-    let dirs = list(cachedir);
-
-    // Get a list of packages in cachedir
-    if dirs.len() > 1 {
-        info!("Installing AUR dependencies for {}", pkg);
-        let cmd = std::process::Command::new("bash")
-            .args(&[
-                "-cO",
-                "extglob",
-                format!(
-                    "{} pacman -U --asdeps {}/!({})/*.pkg.tar.* {}",
-                    config::read().bin.sudo.unwrap_or_default(),
-                    cachedir,
-                    pkg,
-                    if options.noconfirm { "--noconfirm" } else { "" }
-                )
-                .as_str(),
-            ])
-            .spawn()
-            .unwrap()
-            .wait()
-            .unwrap();
-        if cmd.success() {
-            info!("All AUR dependencies for package {} installed", pkg);
-        } else {
-            crash!(
-                AppExitCode::PacmanError,
-                "AUR dependencies failed to install"
-            );
+/// Announce the stage packages are entering, giving the user a chance to abort beforehand
+fn enter_stage(stage: Stage, install_state: &InstallState, pkg: &str, options: &Options) {
+    // Fetching and building both execute the PKGBUILD, so only let those through once the user agrees
+    if !options.noconfirm && matches!(stage, Stage::Fetch | Stage::Build) {
+        let proceed = prompt!(default true,
+            "Continue with {} {}?",
+            stage.describe().to_lowercase(),
+            pkg
+        );
+        if !proceed {
+            // If not, crash
+            if install_state.temporary {
+                rm_rf::remove(&install_state.cachedir).ok();
+            }
+            install_state.finish();
+            crash!(AppExitCode::UserCancellation, "Not proceeding");
         }
     }
 
-    // Install package explicitly
-    info!("Installing {}", pkg);
-    let cmd = std::process::Command::new("bash")
-        .args(&[
-            "-c",
+    info!("{} {}", stage.describe(), pkg);
+}
+
+/// What happened to a package base during an install
+enum Outcome {
+    Built,
+    Failed(String),
+    Skipped(String),
+}
+
+/// Returns the `[package.<name>]` settings of a package base, set for either the base or one of its packages
+fn package_config(build: &PlannedBuild) -> ConfigPackage {
+    config::read().package_settings(&build.base, &build.packages)
+}
+
+/// Quotes a value for a shell script
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Returns a makepkg.conf that reads the base config and then sets the given variables.
+/// makepkg.conf sets variables like `CFLAGS` itself, which would override them in the environment.
+fn override_conf(base_conf: Option<&str>, env: &BTreeMap<String, String>) -> String {
+    // makepkg reads the drop-ins next to the system config too
+    let mut conf = base_conf.map_or_else(
+        || {
             format!(
-                "sudo pacman -U {}/{}/*.pkg.tar.* {}",
-                cachedir,
-                pkg,
-                if options.noconfirm { "--noconfirm" } else { "" }
+                "source {0}\nfor conf in {0}.d/*.conf; do\n    [[ -r $conf ]] && source \"$conf\"\ndone\n",
+                shell_quote(MAKEPKG_CONF)
             )
-            .as_str(),
-        ])
-        .spawn()
-        .unwrap()
-        .wait()
-        .unwrap();
-    if cmd.success() {
-        info!("{} installed!", pkg);
+        },
+        |base_conf| format!("source {}\n", shell_quote(base_conf)),
+    );
+    for (key, value) in env {
+        writeln!(conf, "{}={}", key, shell_quote(value)).unwrap();
+    }
+
+    conf
+}
+
+/// Prepares makepkg to run in a package base's build directory, with the makepkg.conf and environment from its settings
+fn makepkg_command(cachedir: &str, build: &PlannedBuild) -> ShellCommand {
+    let settings = package_config(build);
+    let mut command = ShellCommand::makepkg().working_dir(format!("{}/{}", cachedir, build.base));
+    if settings.env.is_empty() {
+        if let Some(conf) = settings.makepkg_conf {
+            command = command.arg("--config").arg(conf);
+        }
     } else {
-        crash!(AppExitCode::PacmanError, "{} failed to install", pkg);
+        // Set the variables in a makepkg.conf of its own, so the base config can't override them
+        let path = format!("{}/{}.makepkg.conf", cachedir, build.base);
+        let conf = override_conf(settings.makepkg_conf.as_deref(), &settings.env);
+        fs::write(&path, conf).unwrap_or_else(|e| {
+            crash!(
+                AppExitCode::FailedCreatingPaths,
+                "Couldn't write {}: {}",
+                path,
+                e
+            );
+        });
+        command = command.arg("--config").arg(path);
+    }
+    // Variables makepkg.conf doesn't know about only reach the build through the environment
+    for (key, value) in settings.env {
+        command = command.env(key, value);
+    }
+
+    command
+}
+
+/// Runs makepkg in a package base's build directory, appending its output to the base's log
+fn makepkg(cachedir: &str, build: &PlannedBuild, args: &[&str], log: &str) -> Result<(), String> {
+    let status = makepkg_command(cachedir, build)
+        .args(args)
+        .wait_into_log(log)
+        .map_err(|e| e.to_string())?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("makepkg {} failed ({})", args.join(" "), status))
     }
 }
 
-/// Clone a package from the AUR
-fn clone(pkg: &String, pkgcache: &str, options: &Options) {
+/// Warns about a failed package base, showing the end of its log
+fn report_failure(base: &str, error: &str, log: &str) {
+    warn!("{} failed: {}, the full log is at {}", base, error, log);
+    for line in tail(log, 30) {
+        println!("  {line}");
+    }
+}
+
+/// Clone a package base from the AUR, or update an existing clone, and copy it into the cachedir.
+/// PKGBUILDs from elsewhere are copied straight from their directory.
+fn clone(
+    base: &str,
+    source: Option<&str>,
+    pkgcache: &str,
+    cachedir: &str,
+    log: &str,
+) -> Result<(), String> {
     let url = crate::internal::rpc::URL;
+    let clonedir = source.map_or_else(|| format!("{pkgcache}/{base}"), ToString::to_string);
 
     // See if package is already cloned to AUR_CACHE
-    let dirs = list(pkgcache);
-    if dirs.contains(pkg) {
-        // Enter directory and git pull
-        if options.verbosity > 1 {
-            log!("Updating cached PKGBUILD for {}", pkg);
-        }
-        info!("Updating cached package source");
-        set_current_dir(Path::new(&format!(
-            "{}/{}/{}",
-            env::var("HOME").unwrap(),
-            AUR_CACHE,
-            pkg
-        )))
-        .unwrap();
-        ShellCommand::git()
-            .arg("pull")
-            .wait()
-            .silent_unwrap(AppExitCode::GitError);
-    } else {
-        // Clone package into cachedir
-        if options.verbosity >= 1 {
-            log!("Cloning {} into cachedir", pkg);
-        }
-        info!("Cloning package source");
-        set_current_dir(Path::new(&pkgcache)).unwrap();
-        ShellCommand::git()
-            .arg("clone")
-            .arg(format!("{}/{}", url, pkg))
-            .wait()
-            .silent_unwrap(AppExitCode::GitError);
-        // Enter directory and `makepkg -o` to fetch sources
-        if options.verbosity > 1 {
-            log!("Fetching sources for {}", pkg);
+    if source.is_none() {
+        let git = if Path::new(&clonedir).exists() {
+            ShellCommand::git().args(["-C", &clonedir, "pull"])
+        } else {
+            ShellCommand::git()
+                .arg("clone")
+                .arg(format!("{url}/{base}"))
+                .arg(&clonedir)
+        };
+        let status = git.wait_into_log(log).map_err(|e| e.to_string())?;
+        if !status.success() {
+            return Err(format!("git failed ({status})"));
         }
-        info!("Fetching sources");
-        set_current_dir(Path::new(&format!(
-            "{}/{}/{}",
-            env::var("HOME").unwrap(),
-            AUR_CACHE,
-            pkg
-        )))
-        .unwrap();
-        ShellCommand::makepkg()
-            .arg("-od")
-            .wait()
-            .silent_unwrap(AppExitCode::MakePkgError);
     }
+
+    // Copy package from AUR_CACHE to cachedir, replacing whatever an interrupted copy left behind
+    let builddir = format!("{cachedir}/{base}");
+    if Path::new(&builddir).exists() {
+        rm_rf::remove(&builddir).map_err(|e| e.to_string())?;
+    }
+    let status = Command::new("cp")
+        .arg("-r")
+        .arg(&clonedir)
+        .arg(&builddir)
+        .status()
+        .map_err(|e| e.to_string())?;
+    if !status.success() {
+        return Err(format!("Couldn't copy {clonedir} into {cachedir}"));
+    }
+
+    Ok(())
 }
 
-/// General function to handle installing AUR packages.
-pub fn aur_install(a: Vec<String>, options: Options, orig_cachedir: &str) {
-    // Initialise variables
-    let cachedir = if options.asdeps || !orig_cachedir.is_empty() {
-        orig_cachedir.to_string()
+/// Clones every package base in the plan that isn't cloned yet at the same time.
+/// Failures are recorded in `outcomes` when keeping going, otherwise they abort the install.
+fn clone_all(
+    state: &mut InstallState,
+    pkgcache: &str,
+    outcomes: &mut BTreeMap<String, Outcome>,
+    options: Options,
+) {
+    let verbosity = options.verbosity;
+    let (cachedir, timestamp) = (state.cachedir.clone(), state.timestamp.clone());
+    let bases = state
+        .plan
+        .builds
+        .iter()
+        .filter(|b| !state.reached(&b.base, Step::Cloned))
+        .map(|b| (b.base.clone(), b.source.clone()))
+        .collect::<Vec<(String, Option<String>)>>();
+    if bases.is_empty() {
+        return;
+    }
+    let total = bases.len();
+    let mut done = 0;
+    let mut failed: Vec<String> = vec![];
+
+    let mut sp = spinner!("Cloning package sources...");
+    run_parallel(
+        bases,
+        DOWNLOAD_WORKERS,
+        |(base, source): (String, Option<String>)| {
+            let log = log_path(&base, &timestamp);
+            let result = clone(&base, source.as_deref(), pkgcache, &cachedir, &log);
+            (base, result)
+        },
+        |(base, result)| {
+            done += 1;
+            sp.update(&format!("Cloning package sources... ({done}/{total})"));
+            if verbosity >= 1 {
+                log!("Cloned {}: {:?}", base, result);
+            }
+            match result {
+                Ok(()) => state.complete(&base, Step::Cloned),
+                Err(e) => {
+                    report_failure(&base, &e, &log_path(&base, &timestamp));
+                    outcomes.insert(base.clone(), Outcome::Failed(format!("cloning: {e}")));
+                    failed.push(base);
+                }
+            }
+        },
+    );
+    sp.stop_bold("Cloned package sources");
+
+    if !failed.is_empty() && !options.build.keep_going {
+        crash!(
+            AppExitCode::GitError,
+            "Failed to clone packages {}",
+            failed.join(", ")
+        );
+    }
+}
+
+/// Downloads and verifies the sources of every package base in the plan at the same time.
+/// Extracting them, `prepare()` and `pkgver()` are left to the build, when the AUR makedepends are installed.
+/// Failures are recorded in `outcomes` when keeping going, otherwise they abort the install.
+fn fetch_all(state: &mut InstallState, outcomes: &mut BTreeMap<String, Outcome>, options: Options) {
+    let verbosity = options.verbosity;
+    let (cachedir, timestamp) = (state.cachedir.clone(), state.timestamp.clone());
+    let builds = state
+        .plan
+        .builds
+        .iter()
+        .filter(|b| !outcomes.contains_key(&b.base) && !state.reached(&b.base, Step::Fetched))
+        .cloned()
+        .collect::<Vec<PlannedBuild>>();
+    if builds.is_empty() {
+        return;
+    }
+    let total = builds.len();
+    let mut done = 0;
+    let mut failed: Vec<String> = vec![];
+
+    let mut sp = spinner!("Fetching sources...");
+    run_parallel(
+        builds,
+        DOWNLOAD_WORKERS,
+        |build| {
+            let log = log_path(&build.base, &timestamp);
+            let result = makepkg(&cachedir, &build, &["--verifysource", "--skippgp"], &log);
+            (build.base, result)
+        },
+        |(base, result)| {
+            done += 1;
+            sp.update(&format!("Fetching sources... ({done}/{total})"));
+            if verbosity >= 1 {
+                log!("Fetched sources for {}: {:?}", base, result);
+            }
+            match result {
+                Ok(()) => state.complete(&base, Step::Fetched),
+                Err(e) => {
+                    report_failure(&base, &e, &log_path(&base, &timestamp));
+                    outcomes.insert(base.clone(), Outcome::Failed(e));
+                    failed.push(base);
+                }
+            }
+        },
+    );
+    sp.stop_bold("Fetched sources");
+
+    if !failed.is_empty() && !options.build.keep_going {
+        crash!(
+            AppExitCode::MakePkgError,
+            "Failed to fetch sources for packages {}",
+            failed.join(", ")
+        );
+    }
+}
+
+/// Returns the package files built from a package base, keeping only the wanted packages if makepkg built others too
+fn built_files(build: &PlannedBuild, cachedir: &str) -> Vec<String> {
+    // Ask makepkg which files the PKGBUILD produced, keeping the ones that are wanted
+    let produced = makepkg_command(cachedir, build)
+        .arg("--packagelist")
+        .wait_with_output()
+        .silent_unwrap(AppExitCode::MakePkgError)
+        .stdout
+        .lines()
+        .map(ToString::to_string)
+        .collect::<Vec<String>>();
+    let wanted = produced
+        .iter()
+        .filter(|path| {
+            let filename = path.rsplit('/').next().unwrap_or_default();
+            parse_filename(filename).is_some_and(|(name, _)| build.packages.contains(&name))
+        })
+        .cloned()
+        .collect::<Vec<String>>();
+
+    if wanted.is_empty() {
+        produced
     } else {
-        mktemp()
+        wanted
+    }
+}
+
+/// Copies package files into a directory
+fn copy_built(files: &[String], output: &str) -> Result<(), String> {
+    fs::create_dir_all(output).map_err(|e| format!("Couldn't create {output}: {e}"))?;
+    for file in files {
+        let filename = file.rsplit('/').next().unwrap_or_default();
+        fs::copy(file, Path::new(output).join(filename))
+            .map_err(|e| format!("Couldn't copy {filename} into {output}: {e}"))?;
+    }
+
+    Ok(())
+}
+
+/// Installs the packages built from a package base, as dependencies unless one of them was asked for
+fn install_built(
+    build: &PlannedBuild,
+    files: Vec<String>,
+    log: &str,
+    options: Options,
+) -> Result<(), String> {
+    // With a local repo, packages go through it and are installed by name instead of from their files
+    let local_repo = config::read().local_repo;
+    let targets = match &local_repo {
+        Some(repo) => {
+            local_repo::add(repo, &files, log)?;
+            files
+                .iter()
+                .filter_map(|path| parse_filename(path.rsplit('/').next().unwrap_or_default()))
+                .map(|(name, _)| format!("{}/{}", repo.name, name))
+                .collect()
+        }
+        None => files,
     };
-    let pkgcache = format!("{}/{}", env::var("HOME").unwrap(), AUR_CACHE);
-    let verbosity = options.verbosity;
-    let noconfirm = options.noconfirm;
 
-    if verbosity >= 1 {
-        log!("Installing from AUR: {:?}", &a);
+    let mut pacman_args = vec![if local_repo.is_some() { "-S" } else { "-U" }];
+    if !build.explicit || options.asdeps {
+        pacman_args.push("--asdeps");
+    }
+    if options.noconfirm {
+        pacman_args.push("--noconfirm");
     }
 
-    info!("Installing packages {} from the AUR", a.join(", "));
+    info!("Installing {}", build.packages.join(", "));
+    let status = ShellCommand::pacman()
+        .elevated()
+        .args(&pacman_args)
+        .args(targets)
+        .wait_logged(&[log])
+        .silent_unwrap(AppExitCode::PacmanError);
 
-    let mut failed: Vec<String> = vec![];
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("pacman {} failed ({})", pacman_args[0], status))
+    }
+}
+
+/// Installs a built package base. When only building, the targets' packages are copied to `output` instead,
+/// and only installed if other builds in the plan depend on them.
+fn finish_build(
+    build: &PlannedBuild,
+    plan: &Plan,
+    output: Option<&str>,
+    cachedir: &str,
+    log: &str,
+    options: Options,
+) -> Result<(), String> {
+    let files = built_files(build, cachedir);
 
-    for package in a {
-        // Don't process packages if they are already in the cachedir
-        let dirs = list(&cachedir);
-        if dirs.contains(&package) {
-            continue;
+    if let (Some(output), true) = (output, build.explicit) {
+        copy_built(&files, output)?;
+        info!("Copied {} to {}", build.packages.join(", "), output);
+        if !plan.builds.iter().any(|b| b.aur_deps.contains(&build.base)) {
+            return Ok(());
         }
+    }
+
+    install_built(build, files, log, options)
+}
+
+/// Returns which of the given packages are installed
+fn installed_of(packages: &[String]) -> Vec<String> {
+    if packages.is_empty() {
+        return vec![];
+    }
+
+    // pacman only lists the installed ones
+    ShellCommand::pacman()
+        .args(["-Qq", "--color", "never"])
+        .args(packages)
+        .wait_with_output()
+        .silent_unwrap(AppExitCode::PacmanError)
+        .stdout
+        .lines()
+        .map(ToString::to_string)
+        .collect()
+}
 
-        // Query AUR for package info
-        let rpcres = rpcinfo(&package);
-        if !rpcres.found {
-            // If package isn't found, break
+/// Returns the logs of every package base in an install, for what the install does as a whole
+fn plan_logs(state: &InstallState) -> Vec<String> {
+    state
+        .plan
+        .builds
+        .iter()
+        .map(|b| log_path(&b.base, &state.timestamp))
+        .collect()
+}
+
+/// Removes packages that were only installed for building, along with what they alone pulled in
+fn remove_deps(packages: &[String], what: &str, logs: &[String], options: Options) {
+    if options.verbosity >= 1 {
+        log!("Removing {}: {:?}", what, packages);
+    }
+
+    info!("Removing {} {}", what, packages.join(", "));
+    let status = ShellCommand::pacman()
+        .elevated()
+        .args(["-Rns", "--noconfirm"])
+        .args(packages)
+        .wait_logged(logs)
+        .silent_unwrap(AppExitCode::PacmanError);
+    if !status.success() {
+        warn!("Couldn't remove {} {}", what, packages.join(", "));
+    }
+}
+
+/// Removes the dependencies that were only installed to run `check()`, like `makepkg -r` would
+fn remove_check_deps(state: &InstallState, options: Options) {
+    let installed = installed_of(&state.plan.check_deps);
+    if !installed.is_empty() {
+        remove_deps(&installed, "checkdepends", &plan_logs(state), options);
+    }
+}
+
+/// Removes the dependencies that were only installed to build, asking first unless configured otherwise
+fn remove_make_deps(state: &InstallState, options: Options) {
+    let setting = config::read().base.remove_make_deps;
+    if matches!(setting, RemoveMakeDeps::Never) {
+        return;
+    }
+    let installed = installed_of(&state.plan.make_deps);
+    if installed.is_empty() {
+        return;
+    }
+
+    if matches!(setting, RemoveMakeDeps::Ask)
+        && !options.noconfirm
+        && !prompt!(default true, "Remove makedepends {}?", installed.join(", "))
+    {
+        return;
+    }
+    remove_deps(&installed, "makedepends", &plan_logs(state), options);
+}
+
+/// Describes the builds that are running for the progress display
+fn build_progress(running: &[String], done: usize, total: usize, timestamp: &str) -> String {
+    let status = running
+        .iter()
+        .map(|base| {
+            let last_line = tail(&log_path(base, timestamp), 1).pop();
+            format!("{}: {}", base, last_line.unwrap_or_default().trim())
+        })
+        .collect::<Vec<String>>()
+        .join(" | ");
+    let status = format!("[{done}/{total}] {status}");
+
+    // Keep the status on a single line
+    let width = crossterm::terminal::size().map_or(80, |(w, _)| w as usize) - 4;
+    status.chars().take(width).collect()
+}
+
+/// Marks pending builds whose dependencies failed or were skipped as skipped themselves
+fn skip_dependents(pending: &mut Vec<&PlannedBuild>, outcomes: &mut BTreeMap<String, Outcome>) {
+    // Keep going until nothing changes, so dependents of skipped builds are caught too
+    loop {
+        let skipped = pending.iter().find_map(|build| {
+            build
+                .aur_deps
+                .iter()
+                .find(|dep| {
+                    matches!(
+                        outcomes.get(*dep),
+                        Some(Outcome::Failed(_) | Outcome::Skipped(_))
+                    )
+                })
+                .map(|dep| (build.base.clone(), dep.clone()))
+        });
+        let Some((base, dep)) = skipped else {
             break;
-        }
+        };
+
+        pending.retain(|build| build.base != base);
+        outcomes.insert(
+            base,
+            Outcome::Skipped(format!("depends on {dep}, which wasn't built")),
+        );
+    }
+}
 
-        // Get package name
-        let pkg = &rpcres.package.as_ref().unwrap().name;
-        let ood = rpcres.package.as_ref().unwrap().out_of_date;
+/// Builds every package base in the plan, running up to `jobs` independent builds at once.
+/// Each base is installed as soon as it's built so the ones depending on it can start.
+/// When keeping going, a failure only skips the bases depending on it, otherwise nothing new is started.
+fn build_all(state: &mut InstallState, outcomes: &mut BTreeMap<String, Outcome>, options: Options) {
+    let jobs = config::read().base.jobs.unwrap_or(1).max(1);
+    let plan = state.plan.clone();
+    let (cachedir, timestamp) = (state.cachedir.clone(), state.timestamp.clone());
+    let (cachedir, timestamp) = (cachedir.as_str(), timestamp.as_str());
+    let total = plan.builds.len();
 
-        // If package is out of date, warn user
-        if ood.is_some() {
-            warn!(
-                "Package {} is marked as out of date since [{}], it might be broken, not install or not build properly",
-                pkg,
-                Local.timestamp(ood.unwrap().try_into().unwrap(), 0).date_naive()
-            );
-            let p = prompt!(default false, "Would you like to continue?");
-            if !p {
+    // Build makepkg args
+    let mut makepkg_args = vec!["-rcd", "--skippgp", "--needed"];
+    if options.noconfirm {
+        makepkg_args.push("--noconfirm");
+    }
+
+    // Packages that failed to clone or fetch are already accounted for
+    let mut pending = plan
+        .builds
+        .iter()
+        .filter(|build| !outcomes.contains_key(&build.base))
+        .collect::<Vec<&PlannedBuild>>();
+    let mut running: Vec<String> = vec![];
+    let mut stopped = false;
+    let (tx, rx) = mpsc::channel();
+
+    thread::scope(|s| {
+        let mut sp: Option<Spinner> = None;
+
+        loop {
+            skip_dependents(&mut pending, outcomes);
+
+            // Start builds whose dependencies are installed while there are free jobs
+            while !stopped && running.len() < jobs {
+                let ready = pending.iter().position(|b| {
+                    b.aur_deps
+                        .iter()
+                        .all(|dep| matches!(outcomes.get(dep), Some(Outcome::Built)))
+                });
+                let build = match ready {
+                    Some(i) => pending.remove(i),
+                    None => break,
+                };
+
+                // Packages built before an interruption are reused rather than built again
+                running.push(build.base.clone());
+                let prebuilt = state.reached(&build.base, Step::Built);
+                let (tx, makepkg_args) = (tx.clone(), &makepkg_args);
+                s.spawn(move || {
+                    let log = log_path(&build.base, timestamp);
+                    let settings = package_config(build);
+                    let mut args = makepkg_args.clone();
+                    args.extend(settings.makepkg_flags.iter().map(String::as_str));
+                    if (options.build.nocheck || settings.nocheck) && !args.contains(&"--nocheck") {
+                        args.push("--nocheck");
+                    }
+                    let result = if prebuilt {
+                        Ok(())
+                    } else {
+                        makepkg(cachedir, build, &args, &log)
+                    };
+                    tx.send((build, result)).ok();
+                });
+            }
+
+            if running.is_empty() {
                 break;
             }
-        }
 
-        // Clone package into cachedir
-        clone(pkg, &pkgcache, &options);
+            // Refresh the progress display while waiting for a build to finish
+            let progress = build_progress(&running, outcomes.len(), total, timestamp);
+            match sp.as_mut() {
+                Some(sp) => sp.update(&progress),
+                None => sp = Some(spinner!("{}", progress)),
+            }
+            let Ok((build, result)) = rx.recv_timeout(Duration::from_millis(500)) else {
+                continue;
+            };
+            running.retain(|base| base != &build.base);
+            let log = log_path(&build.base, timestamp);
 
-        // Copy package from AUR_CACHE to cachedir
-        Command::new("cp")
-            .arg("-r")
-            .arg(format!(
-                "{}/{}/{}",
-                env::var("HOME").unwrap(),
-                AUR_CACHE,
-                pkg
-            ))
-            .arg(format!("{}/{}", cachedir, pkg))
-            .spawn()
-            .unwrap()
-            .wait()
-            .unwrap();
-
-        // Sort dependencies and makedepends
-        if verbosity >= 1 {
-            log!("Sorting dependencies and makedepends");
-        }
-        let mut sorted = sort(&rpcres.package.as_ref().unwrap().depends, options);
-        let mut md_sorted = sort(&rpcres.package.as_ref().unwrap().make_depends, options);
-        if verbosity >= 1 {
-            log!("Sorted dependencies for {} are:\n{:?}", pkg, &sorted);
-            log!("Sorted makedepends for {} are:\n{:?}", pkg, &md_sorted);
-        }
+            if let Some(sp) = sp.take() {
+                sp.stop_bold(&format!(
+                    "{} {}",
+                    if result.is_ok() {
+                        "Built"
+                    } else {
+                        "Failed to build"
+                    },
+                    build.base
+                ));
+            }
 
-        // If any dependencies are not found in AUR or repos, crash
-        if !sorted.nf.is_empty() || !md_sorted.nf.is_empty() {
-            crash!(
-                AppExitCode::MissingDeps,
-                "Could not find dependencies {} for package {}, aborting",
-                sorted.nf.join(", "),
-                pkg,
-            );
+            // Install right away so dependents can be built against it
+            let result = result.and_then(|()| {
+                state.complete(&build.base, Step::Built);
+                finish_build(
+                    build,
+                    &plan,
+                    state.output.as_deref(),
+                    cachedir,
+                    &log,
+                    options,
+                )
+            });
+            match result {
+                Ok(()) => {
+                    state.complete(&build.base, Step::Installed);
+                    outcomes.insert(build.base.clone(), Outcome::Built);
+                }
+                Err(e) => {
+                    report_failure(&build.base, &e, &log);
+                    outcomes.insert(build.base.clone(), Outcome::Failed(e));
+                    stopped = !options.build.keep_going;
+                }
+            }
         }
+    });
 
-        // Create newopts struct for installing dependencies
-        let newopts = Options {
-            verbosity,
-            noconfirm,
-            asdeps: true,
-        };
+    // Anything left over never got a chance to build
+    for build in pending {
+        outcomes.insert(
+            build.base.clone(),
+            Outcome::Skipped("not started after an earlier failure".to_string()),
+        );
+    }
+}
 
-        // Get a list of installed packages
-        let installed = ShellCommand::pacman()
-            .elevated()
-            .args(&["-Qq"])
-            .wait_with_output()
-            .silent_unwrap(AppExitCode::PacmanError)
-            .stdout
-            .split_whitespace()
-            .collect::<Vec<&str>>()
-            .iter()
-            .map(|s| (*s).to_string())
-            .collect::<Vec<String>>();
+/// Prints what happened to every package base, along with reasons and logs for failures
+fn print_summary(plan: &Plan, outcomes: &BTreeMap<String, Outcome>, timestamp: &str) {
+    let base_width = plan.builds.iter().map(|b| b.base.len()).max().unwrap_or(0);
 
-        // Remove installed packages from sorted dependencies and makedepends
-        if verbosity >= 1 {
-            log!("Removing installed packages from sorted dependencies and makedepends");
-        }
-        sorted.aur.retain(|x| !installed.contains(x));
-        sorted.repo.retain(|x| !installed.contains(x));
-        md_sorted.aur.retain(|x| !installed.contains(x));
-        md_sorted.repo.retain(|x| !installed.contains(x));
-
-        // Prompt user to review/edit PKGBUILD
-        if !noconfirm {
-            review(&cachedir, pkg, orig_cachedir);
+    info!("Summary:");
+    for build in &plan.builds {
+        let base = format!("{:width$}", build.base, width = base_width).bold();
+        match outcomes.get(&build.base) {
+            Some(Outcome::Built) | None => {
+                println!("  {}  {}", base, format!("{:9}", "succeeded").green());
+            }
+            Some(Outcome::Failed(reason)) => {
+                println!(
+                    "  {}  {}  {}\n  {:width$}  {:9}  {}",
+                    base,
+                    format!("{:9}", "failed").red().bold(),
+                    reason,
+                    "",
+                    "",
+                    log_path(&build.base, timestamp),
+                    width = base_width
+                );
+            }
+            Some(Outcome::Skipped(reason)) => {
+                println!(
+                    "  {}  {}  {}",
+                    base,
+                    format!("{:9}", "skipped").yellow(),
+                    reason
+                );
+            }
         }
+    }
+}
 
-        // Install dependencies and makedepends
-        info!("Moving on to install dependencies");
-        if !sorted.repo.is_empty() {
-            install(&sorted.repo, newopts);
+/// Shows what is about to be built, giving the user a chance to back out of out of date packages
+fn show_plan(plan: &Plan, options: Options) {
+    if !plan.repo_deps.is_empty() {
+        info!(
+            "Missing dependencies from the repos: {}",
+            plan.repo_deps.join(", ")
+        );
+    }
+    if !plan.make_deps.is_empty() {
+        info!(
+            "Only needed to build, can be removed afterwards: {}",
+            plan.make_deps.join(", ")
+        );
+    }
+    if !plan.check_deps.is_empty() {
+        info!(
+            "Only needed to run check(), removed after building: {}",
+            plan.check_deps.join(", ")
+        );
+    }
+
+    info!("Building {} package bases:", plan.builds.len());
+    for build in &plan.builds {
+        println!(
+            "  {} {}{}",
+            build.base.bold(),
+            build.version,
+            match (&build.source, build.explicit) {
+                (Some(dir), _) => format!(" (from {dir})"),
+                (None, true) => String::new(),
+                (None, false) => " (dependency)".to_string(),
+            }
+        );
+
+        // Point out anything configured for this package in particular
+        let settings = package_config(build);
+        let mut notes: Vec<String> = vec![];
+        if !settings.makepkg_flags.is_empty() {
+            notes.push(format!(
+                "makepkg flags: {}",
+                settings.makepkg_flags.join(" ")
+            ));
         }
-        if !sorted.aur.is_empty() {
-            aur_install(sorted.aur, newopts, &cachedir.clone());
+        if !settings.env.is_empty() {
+            let env = settings
+                .env
+                .iter()
+                .map(|(key, value)| format!("{key}={value}"))
+                .collect::<Vec<String>>();
+            notes.push(format!("env: {}", env.join(" ")));
         }
-        if !md_sorted.repo.is_empty() {
-            install(&md_sorted.repo, newopts);
+        if let Some(conf) = &settings.makepkg_conf {
+            notes.push(format!("makepkg.conf: {conf}"));
         }
-        if !md_sorted.aur.is_empty() {
-            aur_install(md_sorted.aur, newopts, &cachedir.clone());
+        if settings.skip_review {
+            notes.push("review skipped".to_string());
         }
-
-        // Build makepkg args
-        let mut makepkg_args = vec!["-rcd", "--skippgp", "--needed"];
-        if options.asdeps {
-            makepkg_args.push("--asdeps");
+        if settings.nocheck {
+            notes.push("check skipped".to_string());
         }
-        if options.noconfirm {
-            makepkg_args.push("--noconfirm");
+        if !notes.is_empty() {
+            println!("    {}", notes.join(", "));
         }
+    }
 
-        // Enter cachedir and build package
-        info!("Building time!");
-        set_current_dir(format!("{}/{}", cachedir, pkg)).unwrap();
-        let status = ShellCommand::makepkg()
-            .args(makepkg_args)
-            .wait()
-            .silent_unwrap(AppExitCode::MakePkgError);
-        if !status.success() {
-            // If build failed, push to failed vec
-            failed.push(pkg.clone());
-            return;
+    // If a package is out of date, warn user
+    for build in &plan.builds {
+        if let Some(ood) = build.out_of_date {
+            warn!(
+                "Package {} is marked as out of date since [{}], it might be broken, not install or not build properly",
+                build.base,
+                Local
+                    .timestamp_opt(ood.try_into().unwrap(), 0)
+                    .unwrap()
+                    .date_naive()
+            );
+            if !options.noconfirm && !prompt!(default false, "Would you like to continue?") {
+                crash!(AppExitCode::UserCancellation, "Not proceeding");
+            }
         }
+    }
+}
+
+/// General function to handle installing AUR packages.
+/// Returns the packages that failed to build or were skipped because of it.
+pub fn aur_install(a: &[String], options: Options, orig_cachedir: &str) -> Result<(), Vec<String>> {
+    start(a, &[], options, orig_cachedir, None)
+}
+
+/// Installs AUR packages along with PKGBUILDs that aren't in the AUR, from local directories or git repos
+pub fn aur_install_local(
+    a: &[String],
+    local: &[LocalSource],
+    options: Options,
+    orig_cachedir: &str,
+) -> Result<(), Vec<String>> {
+    start(a, local, options, orig_cachedir, None)
+}
+
+/// Builds AUR packages and their dependencies, leaving the targets' packages in `output` instead of installing them.
+/// AUR dependencies still get installed, since the targets can't be built without them.
+pub fn aur_build(
+    a: &[String],
+    local: &[LocalSource],
+    options: Options,
+    orig_cachedir: &str,
+    output: &str,
+) -> Result<(), Vec<String>> {
+    fs::create_dir_all(output).unwrap_or_else(|e| {
+        crash!(
+            AppExitCode::FailedCreatingPaths,
+            "Couldn't create path: {}: {}",
+            output,
+            e
+        );
+    });
+    // Builds run in other directories, so pin down where the output goes
+    let output =
+        fs::canonicalize(output).map_or_else(|_| output.to_string(), |p| p.display().to_string());
+
+    start(a, local, options, orig_cachedir, Some(output))
+}
 
-        // Return to cachedir
-        set_current_dir(&cachedir).unwrap();
+/// Resolves a plan for AUR packages and runs it from the beginning
+fn start(
+    a: &[String],
+    local: &[LocalSource],
+    options: Options,
+    orig_cachedir: &str,
+    output: Option<String>,
+) -> Result<(), Vec<String>> {
+    // Initialise variables
+    let cachedir = if orig_cachedir.is_empty() {
+        mktemp()
+    } else {
+        orig_cachedir.to_string()
+    };
+    let verbosity = options.verbosity;
 
-        // Finish installation process
-        if !options.asdeps {
-            finish(&cachedir, pkg, &options);
+    if verbosity >= 1 {
+        log!("Installing from AUR: {:?}", a);
+        if !local.is_empty() {
+            log!("Installing from local PKGBUILDs: {:?}", local);
         }
     }
 
-    // If any packages failed to build, warn user with failed packages
-    if !failed.is_empty() {
-        let failed_str = format!("{}.failed", cachedir);
-        warn!(
-            "Failed to build packages {}, keeping cache directory at {} for manual inspection",
-            failed.join(", "),
-            if orig_cachedir.is_empty() {
-                &cachedir
-            } else {
-                &failed_str
+    // Local PKGBUILDs are targets too, known by the packages they build
+    let mut targets = a.to_vec();
+    for source in local {
+        targets.extend(source.info.names.iter().cloned());
+    }
+
+    info!("Installing packages {} from the AUR", targets.join(", "));
+    hooks::pre_transaction(TransactionKind::Install, &targets, options);
+
+    // Work out everything that has to be built, and in which order
+    let plan = Plan::resolve(a, local, options);
+    show_plan(&plan, options);
+
+    // Every package base logs this install to its own file named after when it started
+    let timestamp = Local::now()
+        .naive_local()
+        .format("%Y-%m-%d_%H-%M-%S")
+        .to_string();
+
+    // Keep track of the install next to the build dir so it can be resumed if it gets interrupted
+    let state = InstallState::new(
+        &targets,
+        plan,
+        &cachedir,
+        orig_cachedir.is_empty(),
+        &timestamp,
+        output,
+    );
+    run(state, options)
+}
+
+/// Picks up the last AUR install that didn't finish, reusing the packages it already built
+pub fn resume_aur_install(state: InstallState, options: Options) -> Result<(), Vec<String>> {
+    if options.verbosity >= 1 {
+        log!("Resuming install: {:?}", &state);
+    }
+
+    let installed = state
+        .plan
+        .builds
+        .iter()
+        .filter(|b| state.reached(&b.base, Step::Installed))
+        .count();
+    info!(
+        "Resuming the install of {} from {}, {} of {} package bases are already installed",
+        state.targets.join(", "),
+        state.cachedir,
+        installed,
+        state.plan.builds.len()
+    );
+    hooks::pre_transaction(TransactionKind::Install, &state.targets, options);
+
+    run(state, options)
+}
+
+/// Clones, reviews, fetches, builds and installs everything in an install's plan, skipping the steps already done.
+/// Returns the packages of the bases that failed or were skipped.
+fn run(mut state: InstallState, options: Options) -> Result<(), Vec<String>> {
+    let pkgcache = format!("{}/{}", env::var("HOME").unwrap(), AUR_CACHE);
+    let bases = state
+        .plan
+        .builds
+        .iter()
+        .map(|b| b.base.clone())
+        .collect::<Vec<String>>()
+        .join(", ");
+
+    if let Some(repo) = config::read().local_repo {
+        local_repo::check(&repo);
+    }
+
+    // Package bases installed before an interruption are already done
+    let mut outcomes: BTreeMap<String, Outcome> = state
+        .plan
+        .builds
+        .iter()
+        .filter(|b| state.reached(&b.base, Step::Installed))
+        .map(|b| (b.base.clone(), Outcome::Built))
+        .collect();
+
+    // Clone all package bases into cachedir
+    enter_stage(Stage::Clone, &state, &bases, &options);
+    clone_all(&mut state, &pkgcache, &mut outcomes, options);
+
+    // Prompt user to review/edit PKGBUILDs
+    if !options.noconfirm {
+        let to_review = state
+            .plan
+            .builds
+            .iter()
+            .filter(|b| !outcomes.contains_key(&b.base) && !state.reached(&b.base, Step::Reviewed))
+            .map(|b| (b.base.clone(), package_config(b).skip_review))
+            .collect::<Vec<(String, bool)>>();
+        for (base, skip_review) in to_review {
+            if skip_review {
+                info!("Not reviewing {}, as set in config", base);
+                continue;
             }
-        );
-        if orig_cachedir.is_empty() {
+            enter_stage(Stage::Review, &state, &base, &options);
+            review(&state.cachedir, &base);
+            state.complete(&base, Step::Reviewed);
+        }
+    }
+
+    // Install dependencies and makedepends from the repos
+    if !state.plan.repo_deps.is_empty() {
+        info!("Moving on to install dependencies");
+        let newopts = Options {
+            asdeps: true,
+            ..options
+        };
+        install_logged(&state.plan.repo_deps, newopts, &plan_logs(&state));
+    }
+
+    // Download sources only now that the PKGBUILDs have been reviewed and the repo makedepends are present.
+    // AUR makedepends aren't built yet, so nothing that might need them runs before the build.
+    enter_stage(Stage::Fetch, &state, &bases, &options);
+    fetch_all(&mut state, &mut outcomes, options);
+
+    // Build packages, installing each as soon as it's built
+    enter_stage(Stage::Build, &state, &bases, &options);
+    build_all(&mut state, &mut outcomes, options);
+    let failed = state
+        .plan
+        .builds
+        .iter()
+        .filter(|b| matches!(outcomes.get(&b.base), Some(Outcome::Failed(_))))
+        .map(|b| b.base.clone())
+        .collect::<Vec<String>>();
+    let not_built = state
+        .plan
+        .builds
+        .iter()
+        .filter(|b| !matches!(outcomes.get(&b.base), Some(Outcome::Built) | None))
+        .flat_map(|b| b.packages.iter().cloned())
+        .collect::<Vec<String>>();
+
+    // Keep a copy of everything built so it can be downgraded to later
+    archive_built(&state.cachedir);
+
+    if options.build.keep_going || !failed.is_empty() {
+        print_summary(&state.plan, &outcomes, &state.timestamp);
+    }
+
+    if failed.is_empty() {
+        // Build dependencies are kept around while anything still has to be retried
+        remove_check_deps(&state, options);
+        remove_make_deps(&state, options);
+
+        if let Some(output) = &state.output {
+            info!("Built packages are in {}", output);
+        }
+        state.finish();
+        if state.temporary {
+            let cachedir = &state.cachedir;
+            rm_rf::remove(cachedir).unwrap_or_else(|e|
+                crash!(AppExitCode::Other, "Could not remove cache directory at {}: {}. This could be a permissions issue with fakeroot, try running `sudo rm -rf {}`", cachedir, e, cachedir)
+            );
+        }
+    } else {
+        // If any packages failed to build, warn user with failed packages
+        if state.temporary && !state.cachedir.ends_with(".failed") {
+            let failed_dir = format!("{}.failed", state.cachedir);
             Command::new("mv")
-                .args(&[&cachedir, &format!("{}.failed", cachedir)])
+                .args([&state.cachedir, &failed_dir])
                 .spawn()
                 .unwrap()
                 .wait()
                 .unwrap();
+            state.moved(&failed_dir);
         }
-    } else if !options.asdeps && orig_cachedir.is_empty() {
-        rm_rf::remove(&cachedir).unwrap_or_else(|e|
-            crash!(AppExitCode::Other, "Could not remove cache directory at {}: {}. This could be a permissions issue with fakeroot, try running `sudo rm -rf {}`", cachedir, e, cachedir)
+        warn!(
+            "Failed to build packages {}, keeping cache directory at {} for manual inspection, run `ame install --resume` to try again",
+            failed.join(", "),
+            state.cachedir
+        );
+    }
+
+    hooks::post_transaction(options);
+
+    if not_built.is_empty() {
+        Ok(())
+    } else {
+        Err(not_built)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides_system_conf() {
+        let env = BTreeMap::from([
+            ("CFLAGS".to_string(), "-O3 -march=native".to_string()),
+            ("MAKEFLAGS".to_string(), "-j8".to_string()),
+        ]);
+        assert_eq!(
+            override_conf(None, &env),
+            "source '/etc/makepkg.conf'\n\
+             for conf in '/etc/makepkg.conf'.d/*.conf; do\n    [[ -r $conf ]] && source \"$conf\"\ndone\n\
+             CFLAGS='-O3 -march=native'\n\
+             MAKEFLAGS='-j8'\n"
+        );
+    }
+
+    #[test]
+    fn overrides_custom_conf() {
+        let env = BTreeMap::from([("LDFLAGS".to_string(), "-Wl,-rpath,'$ORIGIN'".to_string())]);
+        assert_eq!(
+            override_conf(Some("/etc/makepkg-custom.conf"), &env),
+            "source '/etc/makepkg-custom.conf'\nLDFLAGS='-Wl,-rpath,'\\''$ORIGIN'\\'''\n"
         );
     }
 }
@@ -0,0 +1,32 @@
+use std::fs;
+
+use crate::internal::exit_code::AppExitCode;
+use crate::internal::logs::latest_log;
+use crate::internal::rpc::rpcinfo;
+use crate::internal::utils::pager;
+use crate::{crash, info, log, Options};
+
+/// Opens the most recent build log of a package in the pager
+pub fn show_log(pkg: &str, options: Options) {
+    let verbosity = options.verbosity;
+
+    // Logs are kept per package base, which may differ from the package's name
+    let path = latest_log(pkg).or_else(|| {
+        let base = rpcinfo(pkg).package?.base?;
+        if verbosity >= 1 {
+            log!("{} is built from package base {}", pkg, base);
+        }
+        latest_log(&base)
+    });
+    let path = path.unwrap_or_else(|| {
+        crash!(AppExitCode::Other, "No build logs found for {}", pkg);
+    });
+
+    info!("Showing {}", path);
+    let text = fs::read_to_string(&path).unwrap_or_else(|e| {
+        crash!(AppExitCode::Other, "Couldn't read {}: {}", path, e);
+    });
+
+    #[allow(let_underscore_drop)]
+    let _ = pager(&text);
+}
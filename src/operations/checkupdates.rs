@@ -0,0 +1,57 @@
+use crate::args::CheckUpdatesArgs;
+use crate::internal::exit_code::AppExitCode;
+use crate::internal::foreign::list_foreign;
+use crate::internal::pacman_conf::Ignored;
+use crate::internal::updates::{aur_updates, check_aur, repo_updates};
+use crate::{crash, log, Options};
+
+/// Prints available repo and AUR updates without needing root or touching the system databases.
+/// Packages ignored in pacman.conf or amethyst's config are left out.
+/// Exits with [`AppExitCode::NoUpdates`] when there is nothing to upgrade.
+pub fn checkupdates(args: &CheckUpdatesArgs, options: Options) {
+    let verbosity = options.verbosity;
+    let both = !args.repo && !args.aur;
+
+    let mut updates = vec![];
+    if args.repo || both {
+        updates.extend(repo_updates(options).unwrap_or_else(|e| {
+            crash!(
+                AppExitCode::PacmanError,
+                "Couldn't check for repo updates: {}",
+                e
+            );
+        }));
+    }
+    if args.aur || both {
+        let check = check_aur(list_foreign(options), options, None).unwrap_or_else(|e| {
+            crash!(
+                AppExitCode::RpcError,
+                "Couldn't check for AUR updates: {}",
+                e
+            );
+        });
+        updates.extend(aur_updates(&check));
+    }
+
+    let ignored = Ignored::read();
+    updates.retain(|update| !ignored.contains(&update.name));
+
+    if verbosity >= 1 {
+        log!("Found {} updates", updates.len());
+    }
+
+    if args.json {
+        println!("{}", serde_json::to_string(&updates).unwrap());
+    } else {
+        for update in &updates {
+            println!(
+                "{} {} -> {} [{}]",
+                update.name, update.old_version, update.new_version, update.source
+            );
+        }
+    }
+
+    if updates.is_empty() {
+        std::process::exit(AppExitCode::NoUpdates as i32);
+    }
+}
@@ -0,0 +1,182 @@
+use std::{env, fs};
+
+use crate::internal::commands::ShellCommand;
+use crate::internal::error::SilentUnwrap;
+use crate::internal::exit_code::AppExitCode;
+use crate::internal::foreign::list_foreign;
+use crate::internal::pacman_conf::Ignored;
+use crate::internal::updates::{
+    aur_updates, check_aur, read_cache, repo_updates, write_cache, UpdateCache,
+};
+use crate::{crash, info, log, Options};
+
+const UNIT_NAME: &str = "ame-checkupdates";
+const SYSTEMD_USER_DIR: &str = ".config/systemd/user";
+
+fn unit_path(extension: &str) -> String {
+    format!(
+        "{}/{}/{}.{}",
+        env::var("HOME").unwrap(),
+        SYSTEMD_USER_DIR,
+        UNIT_NAME,
+        extension
+    )
+}
+
+/// Writes and enables a systemd user timer that periodically runs `ame daemon check`
+pub fn install_timer(interval: &str, options: Options) {
+    let verbosity = options.verbosity;
+    let exe = env::current_exe().unwrap_or_else(|e| {
+        crash!(
+            AppExitCode::Other,
+            "Couldn't find the ame executable: {}",
+            e
+        );
+    });
+
+    let service = format!(
+        "\
+[Unit]
+Description=Check for repo and AUR updates with amethyst
+
+[Service]
+Type=oneshot
+ExecStart={} daemon check
+",
+        exe.display()
+    );
+    // `Persistent=` only works with `OnCalendar=`, checks missed while powered off happen through `OnBootSec=` instead
+    let timer = format!(
+        "\
+[Unit]
+Description=Periodically check for repo and AUR updates with amethyst
+
+[Timer]
+OnBootSec=5min
+OnUnitActiveSec={interval}
+
+[Install]
+WantedBy=timers.target
+"
+    );
+
+    fs::create_dir_all(format!(
+        "{}/{}",
+        env::var("HOME").unwrap(),
+        SYSTEMD_USER_DIR
+    ))
+    .and_then(|()| fs::write(unit_path("service"), service))
+    .and_then(|()| fs::write(unit_path("timer"), timer))
+    .unwrap_or_else(|e| {
+        crash!(
+            AppExitCode::FailedCreatingPaths,
+            "Couldn't write systemd units: {}",
+            e
+        );
+    });
+
+    if verbosity >= 1 {
+        log!("Wrote {} and {}", unit_path("service"), unit_path("timer"));
+    }
+
+    ShellCommand::systemctl()
+        .args(["--user", "daemon-reload"])
+        .wait_success()
+        .silent_unwrap(AppExitCode::Other);
+    ShellCommand::systemctl()
+        .args(["--user", "enable", "--now"])
+        .arg(format!("{UNIT_NAME}.timer"))
+        .wait_success()
+        .silent_unwrap(AppExitCode::Other);
+
+    info!(
+        "Enabled {}.timer, checking for updates every {}",
+        UNIT_NAME, interval
+    );
+}
+
+/// Disables and removes the update check timer
+pub fn remove_timer(options: Options) {
+    let verbosity = options.verbosity;
+
+    ShellCommand::systemctl()
+        .args(["--user", "disable", "--now"])
+        .arg(format!("{UNIT_NAME}.timer"))
+        .wait()
+        .silent_unwrap(AppExitCode::Other);
+
+    for extension in ["service", "timer"] {
+        if verbosity >= 1 {
+            log!("Removing {}", unit_path(extension));
+        }
+        fs::remove_file(unit_path(extension)).ok();
+    }
+
+    ShellCommand::systemctl()
+        .args(["--user", "daemon-reload"])
+        .wait_success()
+        .silent_unwrap(AppExitCode::Other);
+
+    info!("Removed {}.timer", UNIT_NAME);
+}
+
+/// Checks for updates, caches the result and sends a notification when new updates show up
+pub fn background_check(options: Options) {
+    let verbosity = options.verbosity;
+
+    let mut updates = repo_updates(options).unwrap_or_else(|e| {
+        crash!(
+            AppExitCode::PacmanError,
+            "Couldn't check for repo updates: {}",
+            e
+        );
+    });
+    let check = check_aur(list_foreign(options), options, None).unwrap_or_else(|e| {
+        crash!(
+            AppExitCode::RpcError,
+            "Couldn't check for AUR updates: {}",
+            e
+        );
+    });
+    updates.extend(aur_updates(&check));
+
+    let ignored = Ignored::read();
+    updates.retain(|update| !ignored.contains(&update.name));
+
+    // Only notify about updates that weren't already there on the last check
+    let previous = read_cache().map(|cache| cache.updates).unwrap_or_default();
+    let new = updates
+        .iter()
+        .filter(|update| !previous.contains(update))
+        .collect::<Vec<_>>();
+
+    if verbosity >= 1 {
+        log!("Found {} updates, {} of them new", updates.len(), new.len());
+    }
+
+    if !new.is_empty() {
+        let body = new
+            .iter()
+            .map(|u| format!("{} {} -> {}", u.name, u.old_version, u.new_version))
+            .collect::<Vec<String>>()
+            .join("\n");
+        ShellCommand::notify_send()
+            .args(["--app-name", "Amethyst", "--icon", "system-software-update"])
+            .arg(format!("{} updates available", updates.len()))
+            .arg(body)
+            .wait()
+            .silent_unwrap(AppExitCode::Other);
+    }
+
+    write_cache(&UpdateCache {
+        timestamp: chrono::Local::now().timestamp(),
+        updates,
+    })
+    .unwrap_or_else(|e| {
+        crash!(
+            AppExitCode::FailedCreatingPaths,
+            "Couldn't write update cache: {}",
+            e
+        );
+    });
+}
@@ -0,0 +1,96 @@
+use colored::Colorize;
+
+use crate::internal::archive::cached_versions;
+use crate::internal::commands::ShellCommand;
+use crate::internal::error::SilentUnwrap;
+use crate::internal::exit_code::AppExitCode;
+use crate::internal::pacman_conf::add_ignore_pkg;
+use crate::internal::query::installed_version;
+use crate::{crash, info, log, prompt, Options};
+
+/// Helps the user install a previous version of a package from pacman's cache or amethyst's archive
+pub fn downgrade(pkg: &str, options: Options) {
+    let verbosity = options.verbosity;
+    let installed = installed_version(pkg);
+    let versions = cached_versions(pkg);
+
+    if versions.is_empty() {
+        crash!(
+            AppExitCode::Other,
+            "No cached versions of {} found in pacman's cache or amethyst's archive",
+            pkg
+        );
+    }
+
+    if verbosity >= 1 {
+        log!("Cached versions of {}: {:?}", pkg, &versions);
+    }
+
+    // List all available versions
+    info!("Available versions of {}:", pkg);
+    for (i, version) in versions.iter().enumerate() {
+        println!(
+            "  {} {} {} {}{}",
+            format!("{:>2}", i + 1).bold(),
+            version.name.bold(),
+            version.version.green().bold(),
+            version.path,
+            if installed.as_ref() == Some(&version.version) {
+                " [installed]".cyan().bold()
+            } else {
+                "".normal()
+            }
+        );
+    }
+
+    // Have the user pick one, defaulting to the newest version that isn't installed
+    let default = versions
+        .iter()
+        .position(|v| installed.as_ref() != Some(&v.version))
+        .unwrap_or(0);
+    let choice = if options.noconfirm {
+        default
+    } else {
+        let input = prompt!(input, "Version to install [{}]", default + 1);
+        if input.is_empty() {
+            default
+        } else {
+            match input.parse::<usize>() {
+                Ok(n) if n >= 1 && n <= versions.len() => n - 1,
+                _ => crash!(
+                    AppExitCode::UserCancellation,
+                    "Invalid selection: {}",
+                    input
+                ),
+            }
+        }
+    };
+    let target = &versions[choice];
+
+    // Install the chosen version
+    let mut pacman_args = vec!["-U", target.path.as_str()];
+    if options.noconfirm {
+        pacman_args.push("--noconfirm");
+    }
+    let status = ShellCommand::pacman()
+        .elevated()
+        .args(pacman_args)
+        .wait()
+        .silent_unwrap(AppExitCode::PacmanError);
+    if !status.success() {
+        crash!(
+            AppExitCode::PacmanError,
+            "Failed to install {} {}",
+            pkg,
+            target.version
+        );
+    }
+    info!("Installed {} {}", pkg, target.version);
+
+    // Offer to keep pacman from upgrading it again straight away
+    if !options.noconfirm
+        && prompt!(default false, "Add {} to IgnorePkg so it isn't upgraded again?", pkg)
+    {
+        add_ignore_pkg(pkg);
+    }
+}
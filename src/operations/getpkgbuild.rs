@@ -0,0 +1,67 @@
+use std::fs;
+use std::path::Path;
+
+use crate::internal::commands::ShellCommand;
+use crate::internal::error::SilentUnwrap;
+use crate::internal::exit_code::AppExitCode;
+use crate::internal::rpc::{rpcinfo, URL};
+use crate::{crash, info, log, Options};
+
+/// Clones the AUR repos of packages into a directory, or updates the ones already there
+pub fn getpkgbuild(packages: &[String], output: Option<&str>, options: Options) {
+    let verbosity = options.verbosity;
+    let dir = output.unwrap_or(".");
+
+    // Split packages share one repo, named after their package base
+    let mut bases: Vec<String> = vec![];
+    let mut not_found: Vec<String> = vec![];
+    for pkg in packages {
+        match rpcinfo(pkg).package {
+            Some(package) => {
+                let base = package.base.unwrap_or_else(|| pkg.clone());
+                if !bases.contains(&base) {
+                    bases.push(base);
+                }
+            }
+            None => not_found.push(pkg.clone()),
+        }
+    }
+    if !not_found.is_empty() {
+        crash!(
+            AppExitCode::MissingDeps,
+            "Could not find {} in the AUR",
+            not_found.join(", ")
+        );
+    }
+    if verbosity >= 1 {
+        log!("Package bases to download: {:?}", &bases);
+    }
+
+    fs::create_dir_all(dir).unwrap_or_else(|e| {
+        crash!(
+            AppExitCode::FailedCreatingPaths,
+            "Couldn't create path: {}: {}",
+            dir,
+            e
+        );
+    });
+
+    for base in bases {
+        let path = format!("{dir}/{base}");
+        let git = if Path::new(&path).join(".git").exists() {
+            info!("Updating {}", path);
+            ShellCommand::git().args(["-C", &path, "pull", "--ff-only"])
+        } else {
+            info!("Cloning {} into {}", base, path);
+            ShellCommand::git()
+                .arg("clone")
+                .arg(format!("{URL}/{base}"))
+                .arg(&path)
+        };
+
+        let status = git.wait().silent_unwrap(AppExitCode::GitError);
+        if !status.success() {
+            crash!(AppExitCode::GitError, "Failed to download {}", base);
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use chrono::{Local, TimeZone};
+use colored::Colorize;
+
+use crate::internal::exit_code::AppExitCode;
+use crate::internal::history::{get, read, Outcome, Source, Transaction, TransactionKind};
+use crate::{crash, info};
+
+/// Formats a unix timestamp as a local date and time
+fn format_timestamp(timestamp: i64) -> String {
+    Local
+        .timestamp_opt(timestamp, 0)
+        .unwrap()
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
+
+/// Prints a one-line summary of a transaction
+fn print_summary(transaction: &Transaction) {
+    let names = transaction
+        .packages
+        .iter()
+        .map(|p| p.name.as_str())
+        .collect::<Vec<&str>>();
+    let names = if names.len() > 5 {
+        format!("{} (+{} more)", names[..5].join(", "), names.len() - 5)
+    } else if names.is_empty() {
+        "no changes".to_string()
+    } else {
+        names.join(", ")
+    };
+
+    println!(
+        "{} {} {} {}{}",
+        format!("#{}", transaction.id).bold(),
+        format_timestamp(transaction.timestamp),
+        transaction.kind.to_string().cyan().bold(),
+        names,
+        if transaction.success {
+            "".normal()
+        } else {
+            " [failed]".red().bold()
+        }
+    );
+}
+
+/// Lists recorded transactions, optionally filtered by package and kind
+pub fn list_history(package: Option<&str>, kind: Option<TransactionKind>, limit: Option<usize>) {
+    let mut transactions = read()
+        .into_iter()
+        .filter(|t| kind.map_or(true, |kind| t.kind == kind))
+        .filter(|t| package.map_or(true, |pkg| t.packages.iter().any(|p| p.name == pkg)))
+        .collect::<Vec<Transaction>>();
+
+    if let Some(limit) = limit {
+        let skip = transactions.len().saturating_sub(limit);
+        transactions.drain(..skip);
+    }
+
+    if transactions.is_empty() {
+        info!("No matching transactions recorded");
+        return;
+    }
+
+    for transaction in &transactions {
+        print_summary(transaction);
+    }
+}
+
+/// Shows every package change in a single transaction
+pub fn show_transaction(id: usize) {
+    let transaction = get(id).unwrap_or_else(|| {
+        crash!(AppExitCode::Other, "No transaction with id {} recorded", id);
+    });
+
+    info!(
+        "Transaction #{} ({}) on {}",
+        transaction.id,
+        transaction.kind,
+        format_timestamp(transaction.timestamp)
+    );
+    println!("  Command: {}", transaction.command);
+    println!(
+        "  Status: {}",
+        if transaction.success {
+            "succeeded".green().bold()
+        } else {
+            "failed".red().bold()
+        }
+    );
+
+    for package in &transaction.packages {
+        let source = match package.source {
+            Source::Repo => "repo/".purple().bold(),
+            Source::Aur => "aur/".cyan().bold(),
+        };
+        let outcome = match package.outcome {
+            Outcome::Installed => "installed".green(),
+            Outcome::Upgraded => "upgraded".green(),
+            Outcome::Downgraded => "downgraded".yellow(),
+            Outcome::Removed => "removed".yellow(),
+            Outcome::Failed => "failed".red(),
+        };
+
+        println!(
+            "  {}{} {} -> {} {}",
+            source,
+            package.name.bold(),
+            package.old_version.as_deref().unwrap_or("none"),
+            package.new_version.as_deref().unwrap_or("none"),
+            outcome.bold()
+        );
+    }
+}
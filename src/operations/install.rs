@@ -1,10 +1,17 @@
 use crate::internal::commands::ShellCommand;
 use crate::internal::error::SilentUnwrap;
 use crate::internal::exit_code::AppExitCode;
+use crate::internal::history::TransactionKind;
+use crate::internal::hooks;
 use crate::{crash, info, log, Options};
 
 /// Help the user install a package from the pacman repos
 pub fn install(packages: &[String], options: Options) {
+    install_logged(packages, options, &[]);
+}
+
+/// Installs packages from the pacman repos like [`install`], also copying pacman's output into the given logs
+pub fn install_logged(packages: &[String], options: Options, logs: &[String]) {
     info!("Installing packages {} from repos", &packages.join(", "));
 
     // Build pacman args
@@ -22,13 +29,16 @@ pub fn install(packages: &[String], options: Options) {
             log!("Installing from repos: {:?}", &packages);
         }
 
+        hooks::pre_transaction(TransactionKind::Install, packages, options);
+
         // Install packages
-        let status = ShellCommand::pacman()
-            .elevated()
-            .args(opers)
-            .args(packages)
-            .wait()
-            .silent_unwrap(AppExitCode::PacmanError);
+        let pacman = ShellCommand::pacman().elevated().args(opers).args(packages);
+        let status = if logs.is_empty() {
+            pacman.wait()
+        } else {
+            pacman.wait_logged(logs)
+        }
+        .silent_unwrap(AppExitCode::PacmanError);
         if !status.success() {
             // If pacman failed, crash
             crash!(
@@ -41,5 +51,7 @@ pub fn install(packages: &[String], options: Options) {
         if verbosity >= 1 {
             log!("Installing packages: {:?} was successful", &packages);
         }
+
+        hooks::post_transaction(options);
     }
 }
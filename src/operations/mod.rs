@@ -1,13 +1,31 @@
 pub use aur_install::*;
+pub use build_log::*;
+pub use checkupdates::*;
 pub use clean::*;
+pub use daemon::*;
+pub use downgrade::*;
+pub use getpkgbuild::*;
+pub use history::*;
 pub use install::*;
+pub use news::*;
+pub use rebuild_check::*;
+pub use rollback::*;
 pub use search::{aur_search, repo_search as search};
 pub use uninstall::*;
 pub use upgrade::*;
 
 mod aur_install;
+mod build_log;
+mod checkupdates;
 mod clean;
+mod daemon;
+mod downgrade;
+mod getpkgbuild;
+mod history;
 mod install;
+mod news;
+mod rebuild_check;
+mod rollback;
 mod search;
 mod uninstall;
 mod upgrade;
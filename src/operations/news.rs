@@ -0,0 +1,98 @@
+use colored::Colorize;
+use textwrap::wrap;
+
+use crate::args::NewsArgs;
+use crate::internal::exit_code::AppExitCode;
+use crate::internal::history::last_synced;
+use crate::internal::news::{fetch_news, NewsPost};
+use crate::internal::utils::pager;
+use crate::{crash, info, log, prompt, warn, Options};
+
+/// Formats news posts for display
+fn format_posts(posts: &[NewsPost]) -> String {
+    posts
+        .iter()
+        .map(|post| {
+            let opts = textwrap::Options::new(crossterm::terminal::size().unwrap().0 as usize - 4)
+                .initial_indent("    ")
+                .subsequent_indent("    ");
+
+            format!(
+                "{} {}\n    {}\n\n{}",
+                post.date.date_naive().to_string().green().bold(),
+                post.title.bold(),
+                post.link.cyan(),
+                wrap(&post.description, opts).join("\n")
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n\n")
+}
+
+/// Shows the latest posts from the news feed
+pub fn news(args: &NewsArgs, options: Options) {
+    let verbosity = options.verbosity;
+
+    let mut posts = fetch_news().unwrap_or_else(|e| {
+        crash!(AppExitCode::Other, "Couldn't fetch news: {}", e);
+    });
+    if let Some(limit) = args.limit {
+        posts.truncate(limit);
+    }
+
+    if verbosity >= 1 {
+        log!("Showing {} news posts", posts.len());
+    }
+
+    if posts.is_empty() {
+        info!("No news posts found");
+        return;
+    }
+
+    let text = format_posts(&posts);
+    println!("{text}");
+
+    // Check if the posts are longer than terminal height
+    if text.lines().count() > crossterm::terminal::size().unwrap().1 as usize {
+        // If so, paginate them
+        #[allow(let_underscore_drop)]
+        let _ = pager(&text);
+    }
+}
+
+/// Shows news published since the repos were last upgraded successfully and asks whether to go on upgrading
+pub fn check_news(options: Options) {
+    let verbosity = options.verbosity;
+
+    let posts = match fetch_news() {
+        Ok(posts) => posts,
+        Err(e) => {
+            warn!("Couldn't fetch news, continuing without: {}", e);
+            return;
+        }
+    };
+
+    // Without a recorded upgrade, only the newest post is shown
+    let unread = match last_synced() {
+        Some(since) => posts
+            .into_iter()
+            .filter(|post| post.date.timestamp() > since)
+            .collect::<Vec<NewsPost>>(),
+        None => posts.into_iter().take(1).collect(),
+    };
+
+    if verbosity >= 1 {
+        log!("{} news posts since the last upgrade", unread.len());
+    }
+
+    if unread.is_empty() {
+        return;
+    }
+
+    info!("There is news since your last upgrade, it may require manual intervention:");
+    println!("{}", format_posts(&unread));
+
+    if !options.noconfirm && !prompt!(default true, "Continue with the upgrade?") {
+        crash!(AppExitCode::UserCancellation, "Not proceeding");
+    }
+}
@@ -0,0 +1,50 @@
+use colored::Colorize;
+
+use crate::internal::exit_code::AppExitCode;
+use crate::internal::foreign::list_foreign;
+use crate::internal::rebuild::find_broken;
+use crate::internal::rpc::rpcinfo;
+use crate::operations::aur_install;
+use crate::{crash, info, log, prompt, spinner, warn, Options};
+
+/// Finds AUR packages broken by library or Python upgrades and offers to rebuild them.
+/// Returns the packages that failed to rebuild.
+pub fn rebuild_check(options: Options, cachedir: &str) -> Result<(), Vec<String>> {
+    let verbosity = options.verbosity;
+
+    let sp = spinner!("Scanning foreign packages for broken libraries...");
+    let foreign = list_foreign(options);
+    let broken = find_broken(&foreign, options);
+    sp.stop_bold("Finished!");
+
+    if broken.is_empty() {
+        info!("No foreign packages need rebuilding");
+        return Ok(());
+    }
+
+    for pkg in &broken {
+        println!("{} {}", pkg.name.bold(), pkg.reasons.join(", "));
+    }
+
+    // Only packages still in the AUR can be rebuilt
+    let (rebuildable, gone): (Vec<String>, Vec<String>) = broken
+        .into_iter()
+        .map(|pkg| pkg.name)
+        .partition(|name| rpcinfo(name).found);
+    for name in &gone {
+        warn!("{} is not in the AUR and has to be rebuilt manually", name);
+    }
+    if rebuildable.is_empty() {
+        return Ok(());
+    }
+
+    if verbosity >= 1 {
+        log!("Rebuilding {:?}", &rebuildable);
+    }
+
+    if !options.noconfirm && !prompt!(default true, "Rebuild {} AUR packages?", rebuildable.len()) {
+        crash!(AppExitCode::UserCancellation, "Not proceeding");
+    }
+
+    aur_install(&rebuildable, options, cachedir)
+}
@@ -0,0 +1,138 @@
+use colored::Colorize;
+use std::cmp::Ordering;
+
+use crate::internal::archive::cached_versions;
+use crate::internal::commands::ShellCommand;
+use crate::internal::error::SilentUnwrap;
+use crate::internal::exit_code::AppExitCode;
+use crate::internal::history::get;
+use crate::internal::pacman_conf::add_ignore_pkg;
+use crate::internal::query::installed;
+use crate::internal::utils::vercmp;
+use crate::{crash, info, log, prompt, warn, Options};
+
+/// Reverts every package change made by a recorded transaction.
+pub fn rollback(id: usize, options: Options) {
+    let verbosity = options.verbosity;
+    let transaction = get(id).unwrap_or_else(|| {
+        crash!(AppExitCode::Other, "No transaction with id {} recorded", id);
+    });
+    let versions = installed();
+
+    let mut remove: Vec<String> = vec![];
+    let mut restore: Vec<String> = vec![];
+    let mut downgraded: Vec<String> = vec![];
+    let mut missing: Vec<String> = vec![];
+
+    info!(
+        "Rolling back transaction #{} ({}) would:",
+        transaction.id, transaction.kind
+    );
+    for package in &transaction.packages {
+        let current = versions.get(&package.name);
+        if current != package.new_version.as_ref() {
+            warn!(
+                "{} has changed since transaction #{} ({} -> {})",
+                package.name,
+                transaction.id,
+                package.new_version.as_deref().unwrap_or("none"),
+                current.map_or("none", String::as_str)
+            );
+        }
+
+        match &package.old_version {
+            // Packages the transaction installed get removed again
+            None => {
+                if current.is_some() {
+                    println!("  {} {}", "remove".yellow().bold(), package.name.bold());
+                    remove.push(package.name.clone());
+                }
+            }
+            // Everything else goes back to the version it had before
+            Some(old) if current != Some(old) => {
+                let file = cached_versions(&package.name)
+                    .into_iter()
+                    .find(|v| &v.version == old);
+                if let Some(file) = file {
+                    println!(
+                        "  {} {} {} -> {}",
+                        "restore".green().bold(),
+                        package.name.bold(),
+                        current.map_or("none", String::as_str),
+                        old
+                    );
+                    if current.is_some_and(|c| vercmp(old, c) == Ordering::Less) {
+                        downgraded.push(package.name.clone());
+                    }
+                    restore.push(file.path);
+                } else {
+                    missing.push(format!("{} {}", package.name, old));
+                }
+            }
+            Some(_) => {}
+        }
+    }
+
+    if verbosity >= 1 {
+        log!("Restoring: {:?}\nRemoving: {:?}", &restore, &remove);
+    }
+
+    if !missing.is_empty() {
+        crash!(
+            AppExitCode::Other,
+            "Can't roll back transaction #{}, no cached package files for: {}",
+            transaction.id,
+            missing.join(", ")
+        );
+    }
+    if restore.is_empty() && remove.is_empty() {
+        info!("Nothing to roll back");
+        return;
+    }
+
+    let cont = options.noconfirm || prompt!(default false, "Continue?");
+    if !cont {
+        crash!(AppExitCode::UserCancellation, "Not proceeding");
+    }
+
+    // Restore previous versions first, so anything they replaced can be swapped back
+    if !restore.is_empty() {
+        let mut pacman_args = vec!["-U".to_string()];
+        if options.noconfirm {
+            pacman_args.push("--noconfirm".to_string());
+        }
+        ShellCommand::pacman()
+            .elevated()
+            .args(pacman_args)
+            .args(&restore)
+            .wait_success()
+            .silent_unwrap(AppExitCode::PacmanError);
+    }
+
+    // Remove packages the transaction introduced, skipping any that were swapped out above
+    let versions = installed();
+    remove.retain(|pkg| versions.contains_key(pkg));
+    if !remove.is_empty() {
+        let mut pacman_args = vec!["-R".to_string()];
+        if options.noconfirm {
+            pacman_args.push("--noconfirm".to_string());
+        }
+        ShellCommand::pacman()
+            .elevated()
+            .args(pacman_args)
+            .args(&remove)
+            .wait_success()
+            .silent_unwrap(AppExitCode::PacmanError);
+    }
+
+    info!("Rolled back transaction #{}", transaction.id);
+
+    // Offer to keep pacman from upgrading downgraded packages again
+    if !options.noconfirm && !downgraded.is_empty() {
+        for pkg in downgraded {
+            if prompt!(default false, "Add {} to IgnorePkg so it isn't upgraded again?", pkg) {
+                add_ignore_pkg(&pkg);
+            }
+        }
+    }
+}
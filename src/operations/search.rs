@@ -5,14 +5,14 @@ use textwrap::wrap;
 use crate::internal::commands::ShellCommand;
 use crate::internal::error::SilentUnwrap;
 use crate::internal::exit_code::AppExitCode;
-use crate::internal::rpc::rpcsearch;
+use crate::internal::rpc::{rpcsearch_by, SearchBy};
 use crate::{log, Options};
 
 #[allow(clippy::module_name_repetitions)]
-/// Searches for packages from the AUR and returns wrapped results
-pub fn aur_search(query: &str, options: Options) -> String {
+/// Searches for packages from the AUR by the given field and returns wrapped results
+pub fn aur_search(query: &str, by: SearchBy, options: Options) -> String {
     // Query AUR for package info
-    let res = rpcsearch(query);
+    let res = rpcsearch_by(query, by);
 
     // Get verbosity
     let verbosity = options.verbosity;
@@ -55,9 +55,10 @@ pub fn aur_search(query: &str, options: Options) -> String {
 
     if verbosity > 1 {
         log!(
-            "Found {} results for \"{}\" in the AUR",
+            "Found {} results for \"{}\" by {} in the AUR",
             res.results.len(),
-            query
+            query,
+            by
         );
     }
 
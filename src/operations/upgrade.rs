@@ -1,20 +1,479 @@
+use chrono::{Local, TimeZone};
+use colored::Colorize;
+use std::cmp::Ordering;
+use std::{env, fs};
+
 use crate::args::UpgradeArgs;
 use crate::internal::commands::ShellCommand;
+use crate::internal::config;
 use crate::internal::detect;
 use crate::internal::error::SilentUnwrap;
 use crate::internal::exit_code::AppExitCode;
-use crate::internal::rpc::rpcinfo;
-use crate::operations::aur_install::aur_install;
-use crate::{info, log, prompt, spinner, warn, Options};
+use crate::internal::foreign::{
+    find_moved, find_orphaned, list_foreign, report_orphaned, MovedPackage, OrphanedPackage,
+};
+use crate::internal::history;
+use crate::internal::history::{Source, TransactionKind};
+use crate::internal::hooks;
+use crate::internal::local_source::LocalSource;
+use crate::internal::pacman_conf::Ignored;
+use crate::internal::query::installed;
+use crate::internal::selection::parse_selection;
+use crate::internal::sources::find_installed;
+use crate::internal::updates::{check_aur, read_cache, repo_updates};
+use crate::internal::utils::vercmp;
+use crate::operations::aur_install::aur_install_local;
+use crate::operations::{check_news, install};
+use crate::{crash, info, log, prompt, spinner, warn, Options};
+
+const UPGRADE_EXCLUDE: &str = ".local/share/ame/upgrade_exclude";
 
 #[derive(Debug)]
-struct QueriedPackage {
+/// An installed AUR package with a newer version available
+struct AurUpgrade {
     pub name: String,
-    pub version: String,
+    pub installed: String,
+    pub new: String,
+    pub out_of_date: Option<usize>,
+    pub last_modified: Option<i64>,
+    /// The PKGBUILD to build it from if it isn't in the AUR
+    pub source: Option<LocalSource>,
+}
+
+/// Reads the packages the user chose to leave out of upgrades by default
+fn read_exclusions() -> Vec<String> {
+    fs::read_to_string(format!("{}/{}", env::var("HOME").unwrap(), UPGRADE_EXCLUDE))
+        .unwrap_or_default()
+        .lines()
+        .map(ToString::to_string)
+        .collect()
+}
+
+/// Stores the packages to leave out of upgrades by default
+fn write_exclusions(excluded: &[String]) {
+    let path = format!("{}/{}", env::var("HOME").unwrap(), UPGRADE_EXCLUDE);
+    fs::write(&path, excluded.join("\n")).unwrap_or_else(|e| {
+        crash!(
+            AppExitCode::FailedCreatingPaths,
+            "Couldn't write {}: {}",
+            path,
+            e
+        );
+    });
+}
+
+/// Formats a unix timestamp as a date, or an empty string if absent
+fn format_date(timestamp: Option<i64>) -> String {
+    timestamp
+        .and_then(|t| Local.timestamp_opt(t, 0).single())
+        .map(|t| t.date_naive().to_string())
+        .unwrap_or_default()
+}
+
+/// Prints a numbered table of available AUR upgrades
+fn print_upgrade_table(upgrades: &[AurUpgrade], excluded: &[String]) {
+    let name_width = upgrades.iter().map(|u| u.name.len()).max().unwrap_or(0);
+    let installed_width = upgrades
+        .iter()
+        .map(|u| u.installed.len())
+        .max()
+        .unwrap_or(0);
+    let new_width = upgrades.iter().map(|u| u.new.len()).max().unwrap_or(0);
+
+    println!(
+        "{:>3}  {:name_width$}  {:installed_width$}  {:new_width$}  {:10}  Flags",
+        "#",
+        "Name",
+        "Installed",
+        "New",
+        "Modified",
+        name_width = name_width,
+        installed_width = installed_width,
+        new_width = new_width,
+    );
+    for (i, upgrade) in upgrades.iter().enumerate() {
+        let mut flags = vec![];
+        if let Some(ood) = upgrade.out_of_date {
+            flags.push(
+                format!("out of date since {}", format_date(ood.try_into().ok()))
+                    .red()
+                    .bold()
+                    .to_string(),
+            );
+        }
+        if let Some(source) = &upgrade.source {
+            flags.push(format!("from {}", source.dir).cyan().to_string());
+        }
+        if excluded.contains(&upgrade.name) {
+            flags.push("excluded".yellow().bold().to_string());
+        }
+
+        println!(
+            "{}  {}  {:installed_width$}  {}  {:10}  {}",
+            format!("{:>3}", i + 1).bold(),
+            format!("{:width$}", upgrade.name, width = name_width).bold(),
+            upgrade.installed,
+            format!("{:width$}", upgrade.new, width = new_width)
+                .green()
+                .bold(),
+            format_date(upgrade.last_modified),
+            flags.join(", "),
+            installed_width = installed_width,
+        );
+    }
+}
+
+/// Lets the user pick which AUR upgrades to install, returning the chosen package names
+fn select_upgrades(upgrades: &[AurUpgrade], options: Options) -> Vec<String> {
+    let stored_exclusions = read_exclusions();
+
+    print_upgrade_table(upgrades, &stored_exclusions);
+
+    // By default, upgrade everything the user hasn't excluded before
+    let defaults = upgrades
+        .iter()
+        .map(|u| !stored_exclusions.contains(&u.name))
+        .collect::<Vec<bool>>();
+    let selected = if options.noconfirm {
+        defaults
+    } else {
+        let input = prompt!(
+            input,
+            "Packages to exclude (e.g. `1 3-5`, or `^2` to only upgrade 2; leave empty for all{})",
+            if stored_exclusions.is_empty() {
+                ""
+            } else {
+                " but excluded"
+            }
+        );
+        if input.is_empty() {
+            defaults
+        } else {
+            parse_selection(&input, upgrades.len()).unwrap_or_else(|e| {
+                crash!(AppExitCode::UserCancellation, "Invalid selection: {}", e);
+            })
+        }
+    };
+
+    // Offer to remember a changed set of exclusions for the next run
+    let excluded = upgrades
+        .iter()
+        .zip(&selected)
+        .filter(|(_, s)| !**s)
+        .map(|(u, _)| u.name.clone())
+        .collect::<Vec<String>>();
+    let previously_excluded = stored_exclusions
+        .iter()
+        .filter(|e| upgrades.iter().any(|u| &&u.name == e))
+        .cloned()
+        .collect::<Vec<String>>();
+    if !options.noconfirm
+        && excluded != previously_excluded
+        && prompt!(default false,
+            "Remember this selection for future upgrades (excluding {})?",
+            if excluded.is_empty() { "nothing".to_string() } else { excluded.join(", ") }
+        )
+    {
+        // Keep exclusions for packages that just don't have an upgrade right now
+        let mut exclusions = stored_exclusions
+            .into_iter()
+            .filter(|e| !upgrades.iter().any(|u| &u.name == e))
+            .collect::<Vec<String>>();
+        exclusions.extend(excluded);
+        write_exclusions(&exclusions);
+    }
+
+    upgrades
+        .iter()
+        .zip(selected)
+        .filter(|(_, s)| *s)
+        .map(|(u, _)| u.name.clone())
+        .collect()
+}
+
+/// Checks installed foreign packages against the AUR, returning upgrade candidates and orphans
+fn check_aur_upgrades(options: Options) -> (Vec<AurUpgrade>, Vec<OrphanedPackage>) {
+    let verbosity = options.verbosity;
+    let noconfirm = options.noconfirm;
+
+    if verbosity >= 1 {
+        log!("Checking AUR upgrades...");
+    }
+
+    // Start spinner
+    let mut sp = spinner!("Checking AUR upgrades...");
+
+    // List non-native packages using `pacman -Qm`
+    let mut parsed_non_native = list_foreign(options);
+
+    // Offer to switch packages that moved into the repos over to the repo version
+    let moved = find_moved(&parsed_non_native, options);
+    if !moved.is_empty() {
+        sp.stop_bold("Some AUR packages are now available from the repos");
+        for pkg in &moved {
+            info!(
+                "{} is now available as {} from the repos ({})",
+                pkg.name, pkg.repo_package, pkg.relation
+            );
+        }
+
+        // A package only providing the same name might be something else entirely, so don't switch to those by default
+        let (provided, replaced): (Vec<&MovedPackage>, Vec<&MovedPackage>) =
+            moved.iter().partition(|pkg| pkg.relation == "provides");
+        let names = |pkgs: &[&MovedPackage]| {
+            pkgs.iter()
+                .map(|pkg| pkg.name.clone())
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+        let mut switching: Vec<&MovedPackage> = vec![];
+        if !noconfirm
+            && !replaced.is_empty()
+            && prompt!(default true, "Switch {} to the repo versions?", names(&replaced))
+        {
+            switching.extend(&replaced);
+        }
+        if !noconfirm
+            && !provided.is_empty()
+            && prompt!(default false, "Switch {} to the repo packages providing them?", names(&provided))
+        {
+            switching.extend(&provided);
+        }
+        if !switching.is_empty() {
+            let mut repo_packages = switching
+                .iter()
+                .map(|pkg| pkg.repo_package.clone())
+                .collect::<Vec<String>>();
+            repo_packages.sort();
+            repo_packages.dedup();
+            install(&repo_packages, options);
+            parsed_non_native.retain(|pkg| !switching.iter().any(|m| m.name == pkg.name));
+        }
+
+        sp = spinner!("Checking AUR upgrades...");
+    }
+
+    // Check if AUR package versions are the same as installed
+    let check = check_aur(parsed_non_native, options, Some(&mut sp)).unwrap_or_else(|e| {
+        crash!(
+            AppExitCode::RpcError,
+            "Couldn't check for AUR upgrades: {}",
+            e
+        );
+    });
+    let candidates = check
+        .upgrades
+        .into_iter()
+        .map(|(pkg, aur_package)| AurUpgrade {
+            name: pkg.name,
+            installed: pkg.version,
+            new: aur_package.version,
+            out_of_date: aur_package.out_of_date,
+            last_modified: aur_package.last_modified,
+            source: None,
+        })
+        .chain(
+            check
+                .source_upgrades
+                .into_iter()
+                .map(|(pkg, source)| AurUpgrade {
+                    name: pkg.name,
+                    installed: pkg.version,
+                    new: source.info.version.clone(),
+                    out_of_date: None,
+                    last_modified: None,
+                    source: Some(source),
+                }),
+        )
+        .collect();
+
+    // Look for successors of packages that vanished from the AUR
+    let orphaned = find_orphaned(check.missing, options);
+
+    sp.stop_bold("Finished!");
+
+    (candidates, orphaned)
+}
+
+/// Builds upgrade candidates from the last background update check, skipping any already applied
+fn cached_aur_upgrades() -> Option<Vec<AurUpgrade>> {
+    let cache = read_cache();
+    if cache.is_none() {
+        warn!("No background update check found, checking the AUR instead");
+    }
+    let cache = cache?;
+
+    info!(
+        "Using the update check from {}",
+        format_date(Some(cache.timestamp))
+    );
+
+    let versions = installed();
+    let candidates = cache
+        .updates
+        .into_iter()
+        .filter(|update| update.source == Source::Aur)
+        .filter_map(|update| {
+            let installed = versions.get(&update.name)?;
+            if vercmp(installed, &update.new_version) != Ordering::Less {
+                return None;
+            }
+            // The cache doesn't say where an update comes from, a matching PKGBUILD outside the AUR is it
+            let source = find_installed(&update.name)
+                .filter(|source| source.info.version == update.new_version);
+            Some(AurUpgrade {
+                name: update.name,
+                installed: installed.clone(),
+                new: update.new_version,
+                out_of_date: None,
+                last_modified: None,
+                source,
+            })
+        })
+        .collect();
+
+    Some(candidates)
+}
+
+/// Finds AUR upgrades, leaving out ignored packages, and lets the user pick which ones to install.
+/// Returns the AUR packages and the PKGBUILDs from outside the AUR to build.
+fn pick_aur_upgrades(args: &UpgradeArgs, options: Options) -> (Vec<String>, Vec<LocalSource>) {
+    let verbosity = options.verbosity;
+
+    // Start from the last background check if asked to, otherwise query the AUR
+    let cached = if args.cached {
+        cached_aur_upgrades()
+    } else {
+        None
+    };
+    let (candidates, orphaned) = cached.map_or_else(
+        || check_aur_upgrades(options),
+        |candidates| (candidates, vec![]),
+    );
+
+    // Collect packages ignored in pacman.conf or amethyst's own config
+    let ignored = Ignored::read();
+    if verbosity >= 1 {
+        log!(
+            "Ignoring packages matching {:?} and group members {:?}",
+            &ignored.patterns,
+            &ignored.group_members
+        );
+    }
+
+    // Keep packages with new versions unless they are ignored
+    let (ignored_upgrades, aur_upgrades): (Vec<AurUpgrade>, Vec<AurUpgrade>) = candidates
+        .into_iter()
+        .partition(|upgrade| ignored.contains(&upgrade.name));
+
+    for ignored in ignored_upgrades {
+        warn!(
+            "{}: ignoring package upgrade ({} => {})",
+            ignored.name, ignored.installed, ignored.new
+        );
+    }
+    report_orphaned(&orphaned);
+
+    // If vector isn't empty, let the user pick which AUR packages to install, effectively upgrading
+    if aur_upgrades.is_empty() {
+        info!("No upgrades available for installed AUR packages");
+        return (vec![], vec![]);
+    }
+    info!(
+        "{} AUR packages have new versions available",
+        aur_upgrades.len()
+    );
+    let selected = select_upgrades(&aur_upgrades, options);
+    if selected.is_empty() {
+        info!("No AUR packages selected for upgrade");
+    }
+
+    // Split packages share their PKGBUILD, so only build each one once
+    let mut aur = vec![];
+    let mut local: Vec<LocalSource> = vec![];
+    for upgrade in aur_upgrades {
+        if !selected.contains(&upgrade.name) {
+            continue;
+        }
+        match upgrade.source {
+            Some(source) => {
+                if !local.iter().any(|l| l.dir == source.dir) {
+                    local.push(source);
+                }
+            }
+            None => aur.push(upgrade.name),
+        }
+    }
+
+    (aur, local)
+}
+
+/// Checks whether there are any hooks to tell about the packages an upgrade touches
+fn hooks_configured() -> bool {
+    let hooks = config::read().hooks;
+    !hooks.pre_transaction.is_empty()
+        || !hooks.post_transaction.is_empty()
+        || hooks.snapshot.is_some()
+}
+
+/// Lists the repo packages `pacman -Syu` is about to upgrade, for the hooks to know about
+fn pending_repo_upgrades(options: Options) -> Vec<String> {
+    // Listing them takes a database sync of its own, so only do it when there are hooks to tell
+    if !hooks_configured() {
+        return vec![];
+    }
+
+    repo_updates(options).map_or_else(
+        |e| {
+            warn!("Couldn't list repo upgrades for the hooks: {}", e);
+            vec![]
+        },
+        |updates| updates.into_iter().map(|update| update.name).collect(),
+    )
+}
+
+/// Lists the AUR packages with new versions before anything is upgraded, for the hooks to know about.
+/// Packages that moved into the repos and the user's selection are only handled after the repo upgrade.
+fn pending_aur_upgrades(args: &UpgradeArgs, options: Options) -> Vec<String> {
+    // Querying the AUR takes a while, so only do it when there are hooks to tell
+    if !hooks_configured() {
+        return vec![];
+    }
+
+    let mut names = if let Some(cache) = read_cache().filter(|_| args.cached) {
+        cache
+            .updates
+            .into_iter()
+            .filter(|update| update.source == Source::Aur)
+            .map(|update| update.name)
+            .collect::<Vec<String>>()
+    } else {
+        let mut sp = spinner!("Checking AUR upgrades...");
+        let check = check_aur(list_foreign(options), options, Some(&mut sp));
+        sp.stop_bold("Finished!");
+        check.map_or_else(
+            |e| {
+                warn!("Couldn't list AUR upgrades for the hooks: {}", e);
+                vec![]
+            },
+            |check| {
+                check
+                    .upgrades
+                    .into_iter()
+                    .map(|(pkg, _)| pkg.name)
+                    .collect()
+            },
+        )
+    };
+
+    let ignored = Ignored::read();
+    names.retain(|name| !ignored.contains(name));
+    names
 }
 
 /// Helps the user upgrade installed packages, repo and AUR.
-pub fn upgrade(options: Options, args: UpgradeArgs, cachedir: &str) {
+/// Returns the AUR packages that failed to upgrade.
+pub fn upgrade(options: Options, args: UpgradeArgs, cachedir: &str) -> Result<(), Vec<String>> {
     // Initialise variables
     let verbosity = options.verbosity;
     let noconfirm = options.noconfirm;
@@ -23,11 +482,30 @@ pub fn upgrade(options: Options, args: UpgradeArgs, cachedir: &str) {
         UpgradeArgs {
             aur: true,
             repo: true,
+            cached: args.cached,
+            keep_going: args.keep_going,
+            nocheck: args.nocheck,
         }
     } else {
         args
     };
 
+    // Show news that may need manual intervention before touching the system
+    if args.repo {
+        check_news(options);
+    }
+
+    // Tell the hooks about everything that's about to be upgraded before touching the system
+    let mut packages = if args.repo {
+        pending_repo_upgrades(options)
+    } else {
+        vec![]
+    };
+    if args.aur {
+        packages.extend(pending_aur_upgrades(&args, options));
+    }
+    hooks::pre_transaction(TransactionKind::Upgrade, &packages, options);
+
     if args.repo {
         // Build pacman args
         let mut pacman_args = vec!["-Syu"];
@@ -48,6 +526,7 @@ pub fn upgrade(options: Options, args: UpgradeArgs, cachedir: &str) {
 
         if pacman_result.success() {
             // If pacman was successful, notify user
+            history::synced();
             info!("Successfully upgraded repo packages");
         } else {
             // Otherwise warn user
@@ -60,93 +539,28 @@ pub fn upgrade(options: Options, args: UpgradeArgs, cachedir: &str) {
         if !cont {
             // If user doesn't want to continue, break
             info!("Exiting");
+            hooks::post_transaction(options);
+            history::finish();
             std::process::exit(AppExitCode::PacmanError as i32);
         }
     }
 
-    if args.aur {
-        if verbosity >= 1 {
-            log!("Checking AUR upgrades...");
-        }
-
-        // Start spinner
-        let sp = spinner!("Checking AUR upgrades...");
-
-        // List non-native packages using `pacman -Qm` and collect to a Vec<String>
-        let non_native = ShellCommand::pacman()
-            .arg("-Qm")
-            .args(&["--color", "never"])
-            .wait_with_output()
-            .silent_unwrap(AppExitCode::PacmanError);
-
-        // Collect by lines to a Vec<String>
-        let mut non_native = non_native.stdout.split('\n').collect::<Vec<&str>>();
-
-        // Remove last element, which is an empty line
-        non_native.pop();
-
-        // Parse non-native packages into a Vec<QueriedPackage>
-        let mut parsed_non_native: Vec<QueriedPackage> = vec![];
-        for pkg in non_native {
-            // Split by space
-            let split = pkg.split(' ').collect::<Vec<&str>>();
-            if verbosity >= 1 {
-                log!("{:?}", split);
-            }
-            // Create QueriedPackage and push it to parsed_non_native
-            let name = split[0].to_string();
-            let version = split[1].to_string();
-            parsed_non_native.push(QueriedPackage { name, version });
-        }
-
-        if verbosity >= 1 {
-            log!("{:?}", &parsed_non_native);
-        }
-
-        // Check if AUR package versions are the same as installed
-        let mut aur_upgrades = vec![];
-        for pkg in parsed_non_native {
-            // Query AUR
-            let rpc_result = rpcinfo(&pkg.name);
-
-            if !rpc_result.found {
-                // If package not found, skip
-                continue;
-            }
-
-            // Run `vercmp` to compare versions
-            let vercmp_result = std::process::Command::new("vercmp")
-                .arg(&pkg.version)
-                .arg(&rpc_result.package.unwrap().version)
-                .output()
-                .unwrap();
-            let vercmp_result = String::from_utf8(vercmp_result.stdout).unwrap();
-            if verbosity >= 1 {
-                log!("Vercmp returned {:?}", vercmp_result);
-            }
-
-            // If versions differ, push to a vector
-            if vercmp_result.trim() == "-1" {
-                aur_upgrades.push(pkg.name);
-            }
-        }
-
-        sp.stop_bold("Finished!");
+    // Only look at AUR packages now, so ones that moved into the repos are found in the synced databases
+    let (selected, local) = if args.aur {
+        pick_aur_upgrades(&args, options)
+    } else {
+        (vec![], vec![])
+    };
+    let result = if selected.is_empty() && local.is_empty() {
+        Ok(())
+    } else {
+        aur_install_local(&selected, &local, options, cachedir)
+    };
 
-        // If vector isn't empty, prompt to install AUR packages from vector, effectively upgrading
-        if aur_upgrades.is_empty() {
-            info!("No upgrades available for installed AUR packages");
-        } else {
-            let cont = prompt!(default true,
-                "AUR packages {} have new versions available, upgrade?",
-                aur_upgrades.join(", "),
-            );
-            if cont {
-                aur_install(aur_upgrades, options, cachedir);
-            };
-        }
-    }
+    hooks::post_transaction(options);
 
     // Check for .pacnew files
     detect();
+
+    result
 }